@@ -0,0 +1,549 @@
+//! The command-line interface. The config is read from a file, built from
+//! flags, or both, where flags override the fields of the config file.
+//! Without subcommand, the config file is given by `CONFIG_PATH`.
+
+use crate::client::{environment, output::Format};
+use anyhow::{anyhow, Context};
+use clap::{Args, Parser, Subcommand};
+use log::info;
+use serde_json::{json, Map, Value};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+#[command(
+    name = "any-client",
+    version,
+    about = "Test http, websocket, and grpc endpoints"
+)]
+pub struct Cli {
+    /// The environment of the config to use
+    #[arg(long, global = true, env = "ANY_CLIENT_ENV")]
+    pub env: Option<String>,
+    /// The only request of a collection to run
+    #[arg(long, global = true, env = "ANY_CLIENT_REQUEST")]
+    pub request: Option<String>,
+    /// The output format of requests, responses, and messages
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "ANY_CLIENT_OUTPUT"
+    )]
+    pub output: Format,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Send an http request
+    Http(HttpArgs),
+    /// Receive messages from a websocket
+    Ws(WebsocketArgs),
+    /// Call a grpc method
+    Grpc(GrpcArgs),
+    /// Run a collection of requests
+    Run {
+        /// The collection file
+        collection: PathBuf,
+    },
+}
+
+/// The flags shared by all clients.
+#[derive(Args)]
+pub struct CommonArgs {
+    /// The config file, whose fields are overridden by flags
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// The url, which replaces `api.url` and, for http and websocket,
+    /// `api.endpoint`
+    #[arg(long)]
+    pub url: Option<String>,
+    /// The request body or message as json or text, or `@file` to read it
+    /// from a file
+    #[arg(long, short)]
+    pub data: Option<String>,
+}
+
+#[derive(Args)]
+pub struct HttpArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The request method, `GET` by default, or `POST` if data is given
+    #[arg(long, short = 'X')]
+    pub method: Option<String>,
+    /// A header, e.g. 'Accept: application/json'
+    #[arg(long, short = 'H')]
+    pub header: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct WebsocketArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// Send lines of stdin as messages while receiving
+    #[arg(long, short)]
+    pub interactive: bool,
+}
+
+#[derive(Args)]
+pub struct GrpcArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// The full method name, e.g. 'package.Service/Method'
+    #[arg(long)]
+    pub method: Option<String>,
+    /// A proto file. Without proto file, server reflection is used
+    #[arg(long)]
+    pub proto: Vec<PathBuf>,
+    /// A metadata entry, e.g. 'x-tenant: my_tenant'
+    #[arg(long, short = 'H')]
+    pub header: Vec<String>,
+}
+
+impl Cli {
+    /// Reads the config, selects the environment, and applies the flags of
+    /// the subcommand.
+    pub fn config(&self) -> Result<Value, anyhow::Error> {
+        let environment = self.env.as_deref();
+
+        match &self.command {
+            None => {
+                let config_path = env::var("CONFIG_PATH")
+                    .context("Missing environment variable 'CONFIG_PATH' or subcommand")?;
+                read_config(Path::new(&config_path), environment)
+            }
+            Some(Command::Run { collection }) => read_config(collection, environment),
+            Some(Command::Http(args)) => {
+                let mut config = base_config(&args.common, "http", environment)?;
+                args.apply(&mut config)?;
+                Ok(config)
+            }
+            Some(Command::Ws(args)) => {
+                let mut config = base_config(&args.common, "websocket", environment)?;
+                args.apply(&mut config)?;
+                Ok(config)
+            }
+            Some(Command::Grpc(args)) => {
+                let mut config = base_config(&args.common, "grpc", environment)?;
+                args.apply(&mut config)?;
+                Ok(config)
+            }
+        }
+    }
+}
+
+impl HttpArgs {
+    fn apply(&self, config: &mut Value) -> Result<(), anyhow::Error> {
+        apply_url(config, self.common.url.as_deref())?;
+
+        let request = object(root(config)?, "request")?;
+
+        if let Some(data) = &self.common.data {
+            let (body, is_json) = read_data(data)?;
+            request.insert("body".to_owned(), body);
+
+            if !is_json {
+                request.insert("body_kind".to_owned(), json!("text"));
+            }
+        }
+
+        match &self.method {
+            Some(method) => {
+                request.insert("method".to_owned(), json!(method));
+            }
+            None if !request.contains_key("method") => {
+                let method = if request.contains_key("body") {
+                    "POST"
+                } else {
+                    "GET"
+                };
+                request.insert("method".to_owned(), json!(method));
+            }
+            None => (),
+        }
+
+        if !self.header.is_empty() {
+            let mut headers = match request.remove("headers") {
+                Some(Value::Array(headers)) => headers,
+                Some(Value::Null) | None => vec![],
+                Some(_) => return Err(anyhow!("Invalid field 'request.headers': expected array")),
+            };
+
+            for header in &self.header {
+                let (key, value) = parse_header(header)?;
+
+                // A header flag replaces the header of the config
+                headers.retain(|header| {
+                    header
+                        .get("key")
+                        .and_then(Value::as_str)
+                        .is_none_or(|header_key| !header_key.eq_ignore_ascii_case(key))
+                });
+                headers.push(json!({ "key": key, "value": value }));
+            }
+
+            request.insert("headers".to_owned(), Value::Array(headers));
+        }
+
+        Ok(())
+    }
+}
+
+impl WebsocketArgs {
+    fn apply(&self, config: &mut Value) -> Result<(), anyhow::Error> {
+        apply_url(config, self.common.url.as_deref())?;
+
+        if self.interactive {
+            root(config)?.insert("interactive".to_owned(), json!(true));
+        }
+
+        let subscription = object(root(config)?, "subscription")?;
+
+        if let Some(data) = &self.common.data {
+            let (message, _) = read_data(data)?;
+            subscription.insert("request".to_owned(), message);
+        }
+
+        Ok(())
+    }
+}
+
+impl GrpcArgs {
+    fn apply(&self, config: &mut Value) -> Result<(), anyhow::Error> {
+        if let Some(url) = &self.common.url {
+            object(root(config)?, "api")?.insert("url".to_owned(), json!(url));
+        }
+
+        let proto = object(root(config)?, "proto")?;
+
+        if let Some(method) = &self.method {
+            proto.insert("method".to_owned(), json!(method));
+        }
+
+        if let Some(data) = &self.common.data {
+            let (request, _) = read_data(data)?;
+            proto.insert("request".to_owned(), request);
+        }
+
+        if !self.proto.is_empty() {
+            // Imports are resolved relative to the directories of the files
+            let mut paths = vec![];
+            let mut files = vec![];
+
+            for proto in &self.proto {
+                let path = match proto.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let file = proto
+                    .file_name()
+                    .ok_or_else(|| anyhow!("Invalid proto file '{}'", proto.display()))?;
+
+                paths.push(json!(path.display().to_string()));
+                files.push(json!(file.to_string_lossy()));
+            }
+
+            paths.dedup();
+            proto.insert("path".to_owned(), Value::Array(paths));
+            proto.insert("file".to_owned(), Value::Array(files));
+            proto.remove("descriptor_set");
+        }
+
+        if !self.header.is_empty() {
+            let metadata = object(proto, "metadata")?;
+
+            for header in &self.header {
+                let (key, value) = parse_header(header)?;
+                metadata.insert(key.to_ascii_lowercase(), json!(value));
+            }
+        }
+
+        let has_proto = proto.contains_key("file") || proto.contains_key("descriptor_set");
+        let api = object(root(config)?, "api")?;
+
+        if !has_proto && !api.contains_key("reflection") {
+            info!("Using server reflection without proto file");
+
+            api.insert("reflection".to_owned(), json!(true));
+        }
+
+        Ok(())
+    }
+}
+
+fn read_config(path: &Path, environment: Option<&str>) -> Result<Value, anyhow::Error> {
+    info!("Reading file '{}'", path.display());
+
+    let config_file = fs::read_to_string(path).context("Can't read file")?;
+    let config = serde_json::from_str(&config_file).context("Can't deserialize json")?;
+
+    environment::select(config, environment)
+}
+
+/// Reads the config file if given, and sets the client of the subcommand.
+fn base_config(
+    args: &CommonArgs,
+    client: &str,
+    environment: Option<&str>,
+) -> Result<Value, anyhow::Error> {
+    let mut config = match &args.config {
+        Some(path) => read_config(path, environment)?,
+        None => json!({}),
+    };
+
+    let config_object = root(&mut config)?;
+
+    match config_object.get("client").and_then(Value::as_str) {
+        Some(config_client) if config_client != client => {
+            return Err(anyhow!(
+                "Can't use config of client '{}' for client '{}'",
+                config_client,
+                client
+            ))
+        }
+        _ => {
+            config_object.insert("client".to_owned(), json!(client));
+        }
+    }
+
+    Ok(config)
+}
+
+/// Sets the complete url, as the endpoint is appended to the url by the
+/// http and websocket clients.
+fn apply_url(config: &mut Value, url: Option<&str>) -> Result<(), anyhow::Error> {
+    let api = object(root(config)?, "api")?;
+
+    if let Some(url) = url {
+        api.insert("url".to_owned(), json!(url));
+        api.insert("endpoint".to_owned(), json!(""));
+    } else if !api.contains_key("endpoint") {
+        api.insert("endpoint".to_owned(), json!(""));
+    }
+
+    Ok(())
+}
+
+fn root(config: &mut Value) -> Result<&mut Map<String, Value>, anyhow::Error> {
+    config
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid config: expected object"))
+}
+
+/// Returns the object of the field, which is created if missing.
+fn object<'a>(
+    parent: &'a mut Map<String, Value>,
+    key: &str,
+) -> Result<&'a mut Map<String, Value>, anyhow::Error> {
+    let value = parent.entry(key).or_insert_with(|| json!({}));
+
+    if value.is_null() {
+        *value = json!({});
+    }
+
+    value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid field '{}': expected object", key))
+}
+
+/// Reads the data, or the file given by `@file`, as json, or as string
+/// otherwise. Returns if the data is json.
+fn read_data(data: &str) -> Result<(Value, bool), anyhow::Error> {
+    let data = match data.strip_prefix('@') {
+        Some(path) => fs::read_to_string(path).context(format!("Can't read file '{}'", path))?,
+        None => data.to_owned(),
+    };
+
+    match serde_json::from_str(&data) {
+        Ok(value) => Ok((value, true)),
+        Err(_) => Ok((Value::String(data), false)),
+    }
+}
+
+fn parse_header(header: &str) -> Result<(&str, &str), anyhow::Error> {
+    match header.split_once(':') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim(), value.trim())),
+        _ => Err(anyhow!(
+            "Invalid header '{}', expected 'name: value'",
+            header
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::config::Config;
+
+    fn config(args: &[&str]) -> Value {
+        let args = ["any-client"].iter().chain(args);
+        Cli::try_parse_from(args).unwrap().config().unwrap()
+    }
+
+    #[test]
+    fn test_http() {
+        let config = config(&[
+            "http",
+            "--url",
+            "http://localhost:8080/items",
+            "-H",
+            "accept: application/json",
+            "--data",
+            r#"{"name":"first"}"#,
+        ]);
+
+        assert_eq!(
+            config,
+            json!({
+                "client": "http",
+                "api": { "url": "http://localhost:8080/items", "endpoint": "" },
+                "request": {
+                    "method": "POST",
+                    "body": { "name": "first" },
+                    "headers": [{ "key": "accept", "value": "application/json" }]
+                }
+            })
+        );
+        // The config is consumed by the http client as is
+        assert!(serde_json::from_str::<Config>(&config.to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_http_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(
+            &path,
+            json!({
+                "client": "http",
+                "api": { "url": "http://localhost:8080", "endpoint": "/items" },
+                "request": {
+                    "method": "GET",
+                    "headers": [
+                        { "key": "Accept", "value": "text/plain" },
+                        { "key": "x-tenant", "value": "my_tenant" }
+                    ]
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config = config(&[
+            "http",
+            "--config",
+            path.to_str().unwrap(),
+            "--method",
+            "DELETE",
+            "--header",
+            "accept: application/json",
+            "--data",
+            "plain text",
+        ]);
+
+        assert_eq!(
+            config,
+            json!({
+                "client": "http",
+                "api": { "url": "http://localhost:8080", "endpoint": "/items" },
+                "request": {
+                    "method": "DELETE",
+                    "body": "plain text",
+                    "body_kind": "text",
+                    "headers": [
+                        { "key": "x-tenant", "value": "my_tenant" },
+                        { "key": "accept", "value": "application/json" }
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_websocket() {
+        assert_eq!(
+            config(&[
+                "ws",
+                "--url",
+                "ws://localhost:8080/ws",
+                "--data",
+                r#"{"type":"subscribe"}"#
+            ]),
+            json!({
+                "client": "websocket",
+                "api": { "url": "ws://localhost:8080/ws", "endpoint": "" },
+                "subscription": { "request": { "type": "subscribe" } }
+            })
+        );
+    }
+
+    #[test]
+    fn test_grpc() {
+        assert_eq!(
+            config(&[
+                "grpc",
+                "--url",
+                "http://localhost:50051",
+                "--method",
+                "echo.Echo/UnaryEcho",
+                "--proto",
+                "tests/proto/echo.proto",
+                "-H",
+                "X-Tenant: my_tenant",
+                "-d",
+                r#"{"message":"hello"}"#,
+            ]),
+            json!({
+                "client": "grpc",
+                "api": { "url": "http://localhost:50051" },
+                "proto": {
+                    "method": "echo.Echo/UnaryEcho",
+                    "request": { "message": "hello" },
+                    "path": ["tests/proto"],
+                    "file": ["echo.proto"],
+                    "metadata": { "x-tenant": "my_tenant" }
+                }
+            })
+        );
+
+        // Server reflection is used without proto file
+        assert_eq!(
+            config(&[
+                "grpc",
+                "--url",
+                "http://localhost:50051",
+                "--method",
+                "echo.Echo/UnaryEcho"
+            ]),
+            json!({
+                "client": "grpc",
+                "api": { "url": "http://localhost:50051", "reflection": true },
+                "proto": { "method": "echo.Echo/UnaryEcho" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        let cli = Cli::try_parse_from(["any-client", "http", "-H", "no colon"]).unwrap();
+        assert_eq!(
+            cli.config().unwrap_err().to_string(),
+            "Invalid header 'no colon', expected 'name: value'"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, json!({ "client": "grpc" }).to_string()).unwrap();
+        let cli =
+            Cli::try_parse_from(["any-client", "http", "-c", path.to_str().unwrap()]).unwrap();
+        assert_eq!(
+            cli.config().unwrap_err().to_string(),
+            "Can't use config of client 'grpc' for client 'http'"
+        );
+    }
+}
@@ -0,0 +1,424 @@
+//! Authentication shared by the http and websocket clients.
+
+use anyhow::{anyhow, Context};
+use http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, PROXY_AUTHORIZATION};
+use log::info;
+use serde::Deserialize;
+use std::{
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// Replaces secrets in logged requests
+pub const REDACTED: &str = "***";
+
+/// Tokens are fetched again shortly before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+    ApiKey {
+        name: String,
+        value: String,
+        #[serde(default, rename = "in")]
+        location: ApiKeyLocation,
+    },
+    /// OAuth2 client credentials grant
+    #[serde(rename = "oauth2_client_credentials")]
+    OAuth2ClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+        #[serde(default)]
+        client_authentication: ClientAuthentication,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+/// How the client id and secret are sent to the token endpoint.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuthentication {
+    /// As basic authorization header
+    #[default]
+    Basic,
+    /// As form fields in the request body
+    Body,
+}
+
+/// The credentials added to a request.
+#[derive(Debug, PartialEq)]
+pub enum Credentials {
+    Header(HeaderName, HeaderValue),
+    Query(String, String),
+}
+
+struct Token {
+    access_token: String,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// Provides the credentials for the configured authentication. OAuth2 tokens
+/// are cached until they expire.
+pub struct Authenticator {
+    auth: Auth,
+    token: Mutex<Option<Token>>,
+}
+
+impl Authenticator {
+    pub fn new(auth: Auth) -> Self {
+        Self {
+            auth,
+            token: Mutex::new(None),
+        }
+    }
+
+    pub async fn credentials(&self) -> Result<Credentials, anyhow::Error> {
+        let credentials = match &self.auth {
+            Auth::Basic { username, password } => {
+                let user_pass = format!("{}:{}", username, password.as_deref().unwrap_or_default());
+                authorization(&format!("Basic {}", base64::encode(user_pass)))?
+            }
+            Auth::Bearer { token } => authorization(&format!("Bearer {}", token))?,
+            Auth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Header,
+            } => Credentials::Header(
+                HeaderName::from_str(name).context(format!("Invalid api key name '{}'", name))?,
+                HeaderValue::from_str(value).context("Invalid api key")?,
+            ),
+            Auth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Query,
+            } => Credentials::Query(name.to_owned(), value.to_owned()),
+            Auth::OAuth2ClientCredentials { .. } => {
+                let access_token = self.access_token().await?;
+                authorization(&format!("Bearer {}", access_token))?
+            }
+        };
+
+        Ok(credentials)
+    }
+
+    /// Returns the cached access token, or fetches a new one if there is none
+    /// or it expired.
+    async fn access_token(&self) -> Result<String, anyhow::Error> {
+        if let Some(token) = self.token.lock().unwrap().as_ref() {
+            match token.expires_at {
+                Some(expires_at) if expires_at <= Instant::now() => (),
+                _ => return Ok(token.access_token.to_owned()),
+            }
+        }
+
+        let token = self.fetch_token().await?;
+        let access_token = token.access_token.to_owned();
+
+        *self.token.lock().unwrap() = Some(token);
+
+        Ok(access_token)
+    }
+
+    async fn fetch_token(&self) -> Result<Token, anyhow::Error> {
+        let (token_url, client_id, client_secret, scope, client_authentication) = match &self.auth {
+            Auth::OAuth2ClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+                client_authentication,
+            } => (
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+                client_authentication,
+            ),
+            _ => return Err(anyhow!("Can't fetch token without oauth2 config")),
+        };
+
+        info!("Fetching oauth2 token from '{}'", token_url);
+
+        let mut form = vec![("grant_type", "client_credentials")];
+
+        if let Some(scope) = scope {
+            form.push(("scope", scope));
+        }
+
+        let mut request = reqwest::Client::new().post(token_url);
+
+        match client_authentication {
+            ClientAuthentication::Basic => {
+                request = request.basic_auth(client_id, Some(client_secret));
+            }
+            ClientAuthentication::Body => {
+                form.push(("client_id", client_id));
+                form.push(("client_secret", client_secret));
+            }
+        }
+
+        let fetched_at = Instant::now();
+        let response = request
+            .form(&form)
+            .send()
+            .await
+            .context("Can't fetch oauth2 token")?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "Can't fetch oauth2 token: status {} with body {}",
+                status,
+                body
+            ));
+        }
+
+        let token_response: TokenResponse = response
+            .json()
+            .await
+            .context("Can't deserialize oauth2 token response")?;
+
+        Ok(Token {
+            access_token: token_response.access_token,
+            expires_at: token_response.expires_in.map(|expires_in| {
+                fetched_at + Duration::from_secs(expires_in).saturating_sub(EXPIRY_MARGIN)
+            }),
+        })
+    }
+}
+
+impl Credentials {
+    /// The name of the header which contains the credentials.
+    pub fn header_name(&self) -> Option<&HeaderName> {
+        match self {
+            Credentials::Header(name, _) => Some(name),
+            Credentials::Query(..) => None,
+        }
+    }
+
+    pub fn add_to_headers(&self, headers: &mut HeaderMap) {
+        if let Credentials::Header(name, value) = self {
+            headers.insert(name, value.to_owned());
+        }
+    }
+
+    pub fn add_to_url(&self, url: &mut Url) {
+        if let Credentials::Query(name, value) = self {
+            url.query_pairs_mut().append_pair(name, value);
+        }
+    }
+}
+
+fn authorization(value: &str) -> Result<Credentials, anyhow::Error> {
+    let mut value = HeaderValue::from_str(value).context("Invalid authorization header")?;
+    value.set_sensitive(true);
+
+    Ok(Credentials::Header(AUTHORIZATION, value))
+}
+
+/// Replaces authorization headers and the credentials in headers and url for
+/// logging.
+pub fn redact(headers: &mut HeaderMap, url: &mut Url, credentials: Option<&Credentials>) {
+    let mut names = vec![AUTHORIZATION, PROXY_AUTHORIZATION];
+
+    match credentials {
+        Some(Credentials::Header(name, _)) => names.push(name.to_owned()),
+        Some(Credentials::Query(name, _)) if url.query_pairs().any(|(key, _)| key == *name) => {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(key, value)| {
+                    let value = if key == *name {
+                        REDACTED.to_owned()
+                    } else {
+                        value.into_owned()
+                    };
+                    (key.into_owned(), value)
+                })
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        _ => (),
+    }
+
+    for name in names {
+        if headers.contains_key(&name) {
+            headers.insert(name, HeaderValue::from_static(REDACTED));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::test_server;
+    use hyper::Body;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    async fn credentials(auth: serde_json::Value) -> Credentials {
+        let auth: Auth = serde_json::from_value(auth).unwrap();
+        Authenticator::new(auth).credentials().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_credentials() {
+        assert_eq!(
+            credentials(json!({ "type": "basic", "username": "user", "password": "secret" })).await,
+            Credentials::Header(
+                AUTHORIZATION,
+                HeaderValue::from_static("Basic dXNlcjpzZWNyZXQ=")
+            )
+        );
+        assert_eq!(
+            credentials(json!({ "type": "bearer", "token": "my_token" })).await,
+            Credentials::Header(AUTHORIZATION, HeaderValue::from_static("Bearer my_token"))
+        );
+        assert_eq!(
+            credentials(json!({ "type": "api_key", "name": "x-api-key", "value": "my_key" })).await,
+            Credentials::Header(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("my_key")
+            )
+        );
+        assert_eq!(
+            credentials(
+                json!({ "type": "api_key", "name": "key", "value": "my_key", "in": "query" })
+            )
+            .await,
+            Credentials::Query("key".to_owned(), "my_key".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_client_credentials() {
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let received = token_requests.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let count = received.fetch_add(1, Ordering::SeqCst) + 1;
+            let authorization = request.headers().get(AUTHORIZATION).cloned();
+
+            async move {
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                assert_eq!(&body[..], b"grant_type=client_credentials&scope=read");
+                assert_eq!(
+                    authorization,
+                    Some(HeaderValue::from_static("Basic aWQ6c2VjcmV0"))
+                );
+
+                let token = json!({
+                    "access_token": format!("token_{}", count),
+                    "token_type": "Bearer",
+                    // The first token expires within the expiry margin
+                    "expires_in": if count == 1 { 5 } else { 3600 }
+                });
+                hyper::Response::new(Body::from(token.to_string()))
+            }
+        });
+        let auth: Auth = serde_json::from_value(json!({
+            "type": "oauth2_client_credentials",
+            "token_url": format!("http://{}/token", addr),
+            "client_id": "id",
+            "client_secret": "secret",
+            "scope": "read"
+        }))
+        .unwrap();
+        let authenticator = Authenticator::new(auth);
+
+        for expected_token in ["token_1", "token_2", "token_2"] {
+            assert_eq!(
+                authenticator.credentials().await.unwrap(),
+                Credentials::Header(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {}", expected_token)).unwrap()
+                )
+            );
+        }
+
+        assert_eq!(token_requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_error() {
+        let addr = test_server::spawn(false, |_| async {
+            hyper::Response::builder()
+                .status(401)
+                .body(Body::from("invalid_client"))
+                .unwrap()
+        });
+        let auth: Auth = serde_json::from_value(json!({
+            "type": "oauth2_client_credentials",
+            "token_url": format!("http://{}/token", addr),
+            "client_id": "id",
+            "client_secret": "secret",
+            "client_authentication": "body"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            Authenticator::new(auth)
+                .credentials()
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Can't fetch oauth2 token: status 401 Unauthorized with body invalid_client"
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer my_token"));
+        headers.insert("x-api-key", HeaderValue::from_static("my_key"));
+        let mut url = Url::parse("http://localhost/?page=1&key=my_key").unwrap();
+
+        redact(
+            &mut headers,
+            &mut url,
+            Some(&Credentials::Header(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("my_key"),
+            )),
+        );
+
+        assert_eq!(headers[AUTHORIZATION], REDACTED);
+        assert_eq!(headers["x-api-key"], REDACTED);
+        assert_eq!(url.as_str(), "http://localhost/?page=1&key=my_key");
+
+        redact(
+            &mut headers,
+            &mut url,
+            Some(&Credentials::Query("key".to_owned(), "my_key".to_owned())),
+        );
+
+        assert_eq!(url.as_str(), "http://localhost/?page=1&key=***");
+    }
+}
@@ -0,0 +1,275 @@
+//! Collections of named requests, which are run in order. Each request is a
+//! complete client config. Values of a response can be extracted into
+//! variables by json path, and used in later requests as placeholders.
+
+use crate::client::{expect, interpolation, json_path, outcome::Outcome, use_client};
+use anyhow::{anyhow, Context};
+use log::info;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const REQUESTS: &str = "requests";
+
+#[derive(Deserialize)]
+pub struct Collection {
+    /// Variables shared by all requests
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+    pub requests: Vec<NamedRequest>,
+}
+
+#[derive(Deserialize)]
+pub struct NamedRequest {
+    pub name: String,
+    /// Json paths of the values extracted from the response by variable name
+    #[serde(default)]
+    pub extract: BTreeMap<String, String>,
+    /// The config of the client
+    #[serde(flatten)]
+    pub config: Map<String, Value>,
+}
+
+pub fn is_collection(config: &Value) -> bool {
+    config.get(REQUESTS).is_some()
+}
+
+/// Runs all requests of the collection in order, or only the request with
+/// the given name. Variables of the environment take precedence over the
+/// variables of the collection, and extracted variables take precedence over
+/// both.
+pub async fn run(
+    config: Value,
+    environment_variables: HashMap<String, String>,
+    request_name: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let collection: Collection =
+        serde_json::from_value(config).context("Can't deserialize collection")?;
+
+    let mut names = HashSet::new();
+
+    for request in &collection.requests {
+        if !names.insert(&request.name) {
+            return Err(anyhow!("Duplicate request name '{}'", request.name));
+        }
+    }
+
+    let mut variables = HashMap::new();
+
+    for (name, value) in collection.variables {
+        // Collection variables may refer to environment variables
+        let value = interpolation::interpolate(value, &environment_variables)
+            .context(format!("Can't interpolate variable '{}'", name))?;
+        variables.insert(name, to_variable(&value));
+    }
+
+    variables.extend(environment_variables);
+
+    let requests: Vec<NamedRequest> = match request_name {
+        Some(request_name) => {
+            let available = collection
+                .requests
+                .iter()
+                .map(|request| request.name.to_owned())
+                .collect::<Vec<_>>();
+            let requests: Vec<NamedRequest> = collection
+                .requests
+                .into_iter()
+                .filter(|request| request.name == request_name)
+                .collect();
+
+            if requests.is_empty() {
+                return Err(anyhow!(
+                    "Unknown request '{}', expected one of: {}",
+                    request_name,
+                    available.join(", ")
+                ));
+            }
+
+            requests
+        }
+        None => collection.requests,
+    };
+
+    let total = requests.len();
+    let mut results = vec![];
+
+    // Failed expectations don't stop the run, but are reported at the end
+    for (i, request) in requests.into_iter().enumerate() {
+        info!("Running request '{}' ({}/{})", request.name, i + 1, total);
+
+        let config = interpolation::interpolate(Value::Object(request.config), &variables)
+            .context(format!("Can't interpolate request '{}'", request.name))?
+            .to_string();
+        // Messages are only kept if they are needed afterwards
+        let keep_messages = expect::is_expected(&config)? || !request.extract.is_empty();
+        let outcome = use_client(config.to_owned(), keep_messages)
+            .await
+            .context(format!("Request '{}' failed", request.name))?;
+        let assertions = expect::check(&config, &outcome).context(format!(
+            "Can't check expectations of request '{}'",
+            request.name
+        ))?;
+        results.push((Some(request.name.to_owned()), assertions));
+
+        extract(&outcome, &request.extract, &mut variables).context(format!(
+            "Can't extract variables from response of request '{}'",
+            request.name
+        ))?;
+    }
+
+    expect::report(&results)
+}
+
+fn extract(
+    outcome: &Outcome,
+    extract: &BTreeMap<String, String>,
+    variables: &mut HashMap<String, String>,
+) -> Result<(), anyhow::Error> {
+    if extract.is_empty() {
+        return Ok(());
+    }
+
+    let body = outcome.body();
+
+    for (variable, path) in extract {
+        let value = json_path::select(&body, path)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No value for variable '{}' at '{}'", variable, path))?;
+
+        // The value isn't logged as it may be a secret
+        info!("Extracted variable '{}' at '{}'", variable, path);
+
+        variables.insert(variable.to_owned(), to_variable(value));
+    }
+
+    Ok(())
+}
+
+/// Strings are used as is, other values as json.
+fn to_variable(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.to_owned(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::test_server;
+    use http::header::AUTHORIZATION;
+    use hyper::Body;
+    use serde_json::json;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    /// Starts a server with a login returning a token, and a user endpoint
+    /// which records the authorization header.
+    fn spawn_server() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let authorization = request
+                .headers()
+                .get(AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_owned())
+                .unwrap_or_default();
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.uri().path(), authorization));
+
+            async move {
+                let body = match request.uri().path() {
+                    "/login" => json!({ "token": "abc", "user": { "id": 7 } }),
+                    _ => json!({}),
+                };
+                hyper::Response::new(Body::from(body.to_string()))
+            }
+        });
+
+        (addr, requests)
+    }
+
+    fn collection() -> Value {
+        json!({
+            "variables": { "base_url": "http://${HOST}" },
+            "requests": [
+                {
+                    "name": "login",
+                    "client": "http",
+                    "api": { "url": "${base_url}", "endpoint": "/login" },
+                    "request": { "method": "POST", "body": { "user": "me" } },
+                    "extract": { "token": "$.token", "user_id": "$.user.id" }
+                },
+                {
+                    "name": "user",
+                    "client": "http",
+                    "api": { "url": "${base_url}", "endpoint": "/users/${user_id}" },
+                    "request": { "method": "GET" },
+                    "auth": { "type": "bearer", "token": "${token}" }
+                }
+            ]
+        })
+    }
+
+    fn variables(addr: SocketAddr) -> HashMap<String, String> {
+        HashMap::from([("HOST".to_owned(), addr.to_string())])
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let (addr, requests) = spawn_server();
+
+        run(collection(), variables(addr), None).await.unwrap();
+
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["/login ", "/users/7 Bearer abc"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_selected_request() {
+        let (addr, requests) = spawn_server();
+
+        run(collection(), variables(addr), Some("login"))
+            .await
+            .unwrap();
+
+        assert_eq!(*requests.lock().unwrap(), vec!["/login "]);
+
+        // Extracted variables are missing if only a later request is run
+        let err = run(collection(), variables(addr), Some("user"))
+            .await
+            .unwrap_err();
+        assert_eq!(format!("{:#}", err), "Can't interpolate request 'user': Unresolved variables: '${user_id}' at $.api.endpoint, '${token}' at $.auth.token");
+
+        let err = run(collection(), variables(addr), Some("logout"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown request 'logout', expected one of: login, user"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_value() {
+        let (addr, _) = spawn_server();
+        let mut collection = collection();
+        collection["requests"][0]["extract"] = json!({ "token": "$.access_token" });
+
+        let err = run(collection, variables(addr), None).await.unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "Can't extract variables from response of request 'login': \
+            No value for variable 'token' at '$.access_token'"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Named environments, e.g. dev, staging, and prod, in a single config. The
+//! config contains an `environments` object with the values which differ from
+//! the base config for each environment.
+
+use anyhow::anyhow;
+use log::info;
+use serde_json::Value;
+
+const ENVIRONMENTS: &str = "environments";
+
+/// Removes the environments from the config and merges the selected
+/// environment over the remaining base config.
+pub fn select(mut config: Value, environment: Option<&str>) -> Result<Value, anyhow::Error> {
+    let environments = match config.as_object_mut() {
+        Some(object) => object.remove(ENVIRONMENTS),
+        None => None,
+    };
+
+    let environment = match environment {
+        Some(environment) => environment,
+        None => return Ok(config),
+    };
+
+    info!("Using environment '{}'", environment);
+
+    let mut environments = match environments {
+        Some(Value::Object(environments)) => environments,
+        Some(_) => return Err(anyhow!("Invalid environments: expected object")),
+        None => {
+            return Err(anyhow!(
+                "Can't use environment '{}' without environments in config",
+                environment
+            ))
+        }
+    };
+
+    match environments.remove(environment) {
+        Some(profile) => {
+            merge(&mut config, profile);
+            Ok(config)
+        }
+        None => Err(anyhow!(
+            "Unknown environment '{}', expected one of: {}",
+            environment,
+            environments
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Merges objects recursively. Any other value, including arrays, replaces
+/// the base value.
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(object)) => {
+            for (key, value) in object {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Value {
+        json!({
+            "client": "http",
+            "api": { "url": "http://localhost:8080", "endpoint": "/items" },
+            "request": { "method": "GET", "headers": [{ "key": "x-env", "value": "dev" }] },
+            "environments": {
+                "prod": {
+                    "api": { "url": "https://example.com" },
+                    "request": { "headers": [] },
+                    "auth": { "type": "bearer", "token": "${PROD_TOKEN}" }
+                },
+                "staging": {
+                    "api": { "url": "https://staging.example.com" }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            select(config(), Some("prod")).unwrap(),
+            json!({
+                "client": "http",
+                "api": { "url": "https://example.com", "endpoint": "/items" },
+                "request": { "method": "GET", "headers": [] },
+                "auth": { "type": "bearer", "token": "${PROD_TOKEN}" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_base() {
+        let mut expected = config();
+        expected.as_object_mut().unwrap().remove(ENVIRONMENTS);
+
+        assert_eq!(select(config(), None).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_unknown_environment() {
+        assert_eq!(
+            select(config(), Some("test")).unwrap_err().to_string(),
+            "Unknown environment 'test', expected one of: prod, staging"
+        );
+        assert_eq!(
+            select(json!({ "client": "http" }), Some("prod"))
+                .unwrap_err()
+                .to_string(),
+            "Can't use environment 'prod' without environments in config"
+        );
+    }
+}
@@ -0,0 +1,670 @@
+//! Expectations on the outcome of a request, e.g. the status code, headers,
+//! and values of the body. Failed expectations are reported in a summary
+//! after the run, and fail the run with a non-zero exit code.
+
+use crate::client::{
+    json_path, json_schema,
+    outcome::{Outcome, ReceivedMessage},
+    output,
+    timeout::deserialize_optional_seconds,
+};
+use anyhow::{anyhow, Context};
+use log::info;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, time::Duration};
+use tonic::Code;
+
+const GRPC_CODES: [(&str, Code); 17] = [
+    ("OK", Code::Ok),
+    ("CANCELLED", Code::Cancelled),
+    ("UNKNOWN", Code::Unknown),
+    ("INVALID_ARGUMENT", Code::InvalidArgument),
+    ("DEADLINE_EXCEEDED", Code::DeadlineExceeded),
+    ("NOT_FOUND", Code::NotFound),
+    ("ALREADY_EXISTS", Code::AlreadyExists),
+    ("PERMISSION_DENIED", Code::PermissionDenied),
+    ("RESOURCE_EXHAUSTED", Code::ResourceExhausted),
+    ("FAILED_PRECONDITION", Code::FailedPrecondition),
+    ("ABORTED", Code::Aborted),
+    ("OUT_OF_RANGE", Code::OutOfRange),
+    ("UNIMPLEMENTED", Code::Unimplemented),
+    ("INTERNAL", Code::Internal),
+    ("UNAVAILABLE", Code::Unavailable),
+    ("DATA_LOSS", Code::DataLoss),
+    ("UNAUTHENTICATED", Code::Unauthenticated),
+];
+
+#[derive(Deserialize, Default)]
+pub struct Expect {
+    /// The http status, e.g. `200`, `"2xx"`, or `"200-204"`, or the grpc
+    /// status, e.g. `"NOT_FOUND"` or `5`
+    pub status: Option<Value>,
+    /// Header values by case-insensitive header name
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Checks of values selected by json path
+    #[serde(default)]
+    pub body: Vec<BodyExpectation>,
+    /// A json schema the body must be valid against
+    #[serde(default, deserialize_with = "deserialize_schema")]
+    pub schema: Option<Value>,
+    /// A websocket message which must be received
+    pub message: Option<MessageExpectation>,
+}
+
+/// A check of the values at the json path. Without `equals` or `matches`,
+/// the path must select a value.
+#[derive(Deserialize)]
+pub struct BodyExpectation {
+    pub path: String,
+    /// The value must be equal
+    pub equals: Option<Value>,
+    /// The value must contain the given fields, see `is_match`
+    pub matches: Option<Value>,
+    /// If false, the path must not select a value
+    pub exists: Option<bool>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MessageExpectation {
+    /// The message must contain the given fields, see `is_match`
+    pub matches: Value,
+    /// Seconds after connecting
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub within: Option<f64>,
+}
+
+impl MessageExpectation {
+    pub fn within(&self) -> Option<Duration> {
+        self.within.map(Duration::from_secs_f64)
+    }
+
+    pub fn is_matched_by(&self, message: &ReceivedMessage) -> bool {
+        is_match(&message.payload, &self.matches)
+            && self.within().is_none_or(|within| message.elapsed <= within)
+    }
+}
+
+/// The result of a single expectation.
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub description: String,
+    /// The reason why the expectation failed
+    pub failure: Option<String>,
+}
+
+impl Assertion {
+    pub fn new(description: String, failure: Option<String>) -> Self {
+        Self {
+            description,
+            failure,
+        }
+    }
+
+    pub fn is_passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            Some(failure) => write!(f, "FAIL {}: {}", self.description, failure),
+            None => write!(f, "PASS {}", self.description),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Config {
+    expect: Option<Expect>,
+}
+
+/// Checks if the config has expectations, which need the messages of the
+/// outcome.
+pub fn is_expected(config_file: &str) -> Result<bool, anyhow::Error> {
+    let config: Config =
+        serde_json::from_str(config_file).context("Can't deserialize expectations")?;
+
+    Ok(config.expect.is_some())
+}
+
+/// Checks the expectations of the config against the outcome.
+pub fn check(config_file: &str, outcome: &Outcome) -> Result<Vec<Assertion>, anyhow::Error> {
+    let config: Config =
+        serde_json::from_str(config_file).context("Can't deserialize expectations")?;
+
+    config
+        .expect
+        .unwrap_or_default()
+        .check(outcome)
+        .context("Invalid expectations")
+}
+
+impl Expect {
+    pub fn check(&self, outcome: &Outcome) -> Result<Vec<Assertion>, anyhow::Error> {
+        let mut assertions = vec![];
+
+        match outcome {
+            Outcome::Http {
+                status, headers, ..
+            } => {
+                if let Some(expected) = &self.status {
+                    let (description, is_passed) = check_http_status(expected, status.as_u16())?;
+                    assertions.push(Assertion::new(
+                        description,
+                        (!is_passed).then(|| format!("got {}", status)),
+                    ));
+                }
+
+                for (name, expected) in &self.headers {
+                    let actual = headers
+                        .get_all(name.as_str())
+                        .iter()
+                        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                        .collect::<Vec<_>>();
+                    let failure = match &actual[..] {
+                        [] => Some("missing".to_owned()),
+                        actual if actual.contains(expected) => None,
+                        actual => Some(format!("got '{}'", actual.join("', '"))),
+                    };
+                    assertions.push(Assertion::new(
+                        format!("header '{}' is '{}'", name, expected),
+                        failure,
+                    ));
+                }
+            }
+            Outcome::Grpc { status, .. } => {
+                let expected = match &self.status {
+                    Some(expected) => parse_grpc_code(expected)?,
+                    None => Code::Ok,
+                };
+                let failure = (status.code() != expected).then(|| {
+                    format!(
+                        "got {}: {}",
+                        grpc_code_name(status.code()),
+                        status.message()
+                    )
+                });
+
+                // Without expected status, only an error status is reported
+                if self.status.is_some() || failure.is_some() {
+                    assertions.push(Assertion::new(
+                        format!("grpc status is {}", grpc_code_name(expected)),
+                        failure,
+                    ));
+                }
+            }
+            Outcome::Websocket { steps, .. } => {
+                if self.status.is_some() {
+                    return Err(anyhow!("Status can't be expected for websocket"));
+                }
+
+                assertions.extend(steps.iter().cloned());
+            }
+        }
+
+        if !self.headers.is_empty() && !matches!(outcome, Outcome::Http { .. }) {
+            return Err(anyhow!("Headers can only be expected for http"));
+        }
+
+        let body = outcome.body();
+
+        for expectation in &self.body {
+            assertions.push(expectation.check(&body)?);
+        }
+
+        if let Some(schema) = &self.schema {
+            let violations = json_schema::validate(&body, schema);
+            assertions.push(Assertion::new(
+                "body is valid against schema".to_owned(),
+                (!violations.is_empty()).then(|| violations.join(", ")),
+            ));
+        }
+
+        if let Some(expected) = &self.message {
+            let messages = match outcome {
+                Outcome::Websocket { messages, .. } => messages,
+                _ => return Err(anyhow!("Messages can only be expected for websocket")),
+            };
+            let description = match expected.within() {
+                Some(within) => {
+                    format!("message matching {} within {:?}", expected.matches, within)
+                }
+                None => format!("message matching {}", expected.matches),
+            };
+            let is_passed = messages
+                .iter()
+                .any(|message| expected.is_matched_by(message));
+            assertions.push(Assertion::new(
+                description,
+                (!is_passed).then(|| format!("none of {} received messages", messages.len())),
+            ));
+        }
+
+        Ok(assertions)
+    }
+}
+
+impl BodyExpectation {
+    fn check(&self, body: &Value) -> Result<Assertion, anyhow::Error> {
+        let values = json_path::select(body, &self.path)?;
+
+        let (description, failure) = match (&self.equals, &self.matches, self.exists) {
+            (Some(expected), _, _) => (
+                format!("'{}' equals {}", self.path, expected),
+                match values.first() {
+                    Some(value) if *value == expected => None,
+                    Some(value) => Some(format!("got {}", value)),
+                    None => Some("missing".to_owned()),
+                },
+            ),
+            (None, Some(expected), _) => (
+                format!("'{}' matches {}", self.path, expected),
+                match values.first() {
+                    Some(value) if is_match(value, expected) => None,
+                    Some(value) => Some(format!("got {}", value)),
+                    None => Some("missing".to_owned()),
+                },
+            ),
+            (None, None, Some(false)) => (
+                format!("'{}' doesn't exist", self.path),
+                values.first().map(|value| format!("got {}", value)),
+            ),
+            (None, None, _) => (
+                format!("'{}' exists", self.path),
+                values.is_empty().then(|| "missing".to_owned()),
+            ),
+        };
+
+        Ok(Assertion::new(description, failure))
+    }
+}
+
+/// Checks if the value matches the expected value: objects must contain the
+/// expected fields with matching values, and any other values must be equal.
+pub fn is_match(value: &Value, expected: &Value) -> bool {
+    match (value, expected) {
+        (Value::Object(object), Value::Object(expected)) => {
+            expected.iter().all(|(key, expected)| {
+                object
+                    .get(key)
+                    .is_some_and(|value| is_match(value, expected))
+            })
+        }
+        (Value::Array(array), Value::Array(expected)) => {
+            array.len() == expected.len()
+                && array
+                    .iter()
+                    .zip(expected)
+                    .all(|(value, expected)| is_match(value, expected))
+        }
+        (value, expected) => value == expected,
+    }
+}
+
+/// Checks the status code against a code, a class like `2xx`, or an
+/// inclusive range like `200-299`.
+/// Deserializes the json schema, which must only use supported keywords.
+fn deserialize_schema<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let schema = Option::<Value>::deserialize(deserializer)?;
+
+    if let Some(schema) = &schema {
+        json_schema::check(schema).map_err(|err| de::Error::custom(format!("{:#}", err)))?;
+    }
+
+    Ok(schema)
+}
+
+fn check_http_status(expected: &Value, status: u16) -> Result<(String, bool), anyhow::Error> {
+    let expected = match expected {
+        Value::String(expected) => expected.trim().to_owned(),
+        expected => expected.to_string(),
+    };
+    let invalid = || anyhow!("Invalid http status '{}'", expected);
+
+    let is_passed = if let Some(class) = expected
+        .strip_suffix("xx")
+        .or_else(|| expected.strip_suffix("XX"))
+    {
+        let class: u16 = class.parse().map_err(|_| invalid())?;
+        status / 100 == class
+    } else if let Some((start, end)) = expected.split_once('-') {
+        let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+        (start..=end).contains(&status)
+    } else {
+        let code: u16 = expected.parse().map_err(|_| invalid())?;
+        status == code
+    };
+
+    Ok((format!("status is {}", expected), is_passed))
+}
+
+fn parse_grpc_code(expected: &Value) -> Result<Code, anyhow::Error> {
+    match expected {
+        Value::Number(number) => number
+            .as_u64()
+            .and_then(|number| GRPC_CODES.get(number as usize))
+            .map(|(_, code)| *code),
+        Value::String(name) => GRPC_CODES
+            .iter()
+            .find(|(code_name, _)| code_name.eq_ignore_ascii_case(name))
+            .map(|(_, code)| *code),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        anyhow!(
+            "Invalid grpc status {}, expected one of: {}",
+            expected,
+            GRPC_CODES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })
+}
+
+pub fn grpc_code_name(code: Code) -> &'static str {
+    GRPC_CODES
+        .iter()
+        .find(|(_, grpc_code)| *grpc_code == code)
+        .map_or("UNKNOWN", |(name, _)| name)
+}
+
+/// Prints the results of all expectations, prefixed by the request name in
+/// collections, and fails if any expectation failed.
+pub fn report(results: &[(Option<String>, Vec<Assertion>)]) -> Result<(), anyhow::Error> {
+    let assertions = results
+        .iter()
+        .flat_map(|(name, assertions)| assertions.iter().map(move |assertion| (name, assertion)))
+        .collect::<Vec<_>>();
+
+    if assertions.is_empty() {
+        return Ok(());
+    }
+
+    let failed = assertions
+        .iter()
+        .filter(|(_, assertion)| !assertion.is_passed())
+        .count();
+
+    let summary = format!(
+        "Expectations: {} passed, {} failed",
+        assertions.len() - failed,
+        failed
+    );
+    let lines = assertions.iter().map(|(name, assertion)| match name {
+        Some(name) => format!("[{}] {}", name, assertion),
+        None => assertion.to_string(),
+    });
+
+    // Stdout is reserved for records in the json output formats
+    if output::is_text() {
+        println!("{}", summary);
+
+        for line in lines {
+            println!("  {}", line);
+        }
+    } else {
+        info!("{}", summary);
+
+        for line in lines {
+            info!("{}", line);
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} expectations failed",
+            failed,
+            assertions.len()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    };
+    use serde_json::json;
+    use tonic::Status;
+
+    fn http_outcome() -> Outcome {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        Outcome::Http {
+            status: StatusCode::CREATED,
+            headers,
+            body: json!({ "id": 7, "user": { "name": "me", "roles": ["admin"] } }),
+        }
+    }
+
+    fn results(expect: Value, outcome: &Outcome) -> Vec<String> {
+        let config = json!({ "client": "http", "expect": expect });
+
+        check(&config.to_string(), outcome)
+            .unwrap()
+            .iter()
+            .map(Assertion::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_http() {
+        let expect = json!({
+            "status": "2xx",
+            "headers": { "Content-Type": "application/json" },
+            "body": [
+                { "path": "$.id", "equals": 7 },
+                { "path": "$.user", "matches": { "name": "me" } },
+                { "path": "$.user.roles[0]" },
+                { "path": "$.error", "exists": false }
+            ],
+            "schema": { "type": "object", "required": ["id"] }
+        });
+
+        assert_eq!(
+            results(expect, &http_outcome()),
+            vec![
+                "PASS status is 2xx",
+                "PASS header 'Content-Type' is 'application/json'",
+                "PASS '$.id' equals 7",
+                "PASS '$.user' matches {\"name\":\"me\"}",
+                "PASS '$.user.roles[0]' exists",
+                "PASS '$.error' doesn't exist",
+                "PASS body is valid against schema",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_failures() {
+        let expect = json!({
+            "status": "200-200",
+            "headers": { "content-type": "text/plain", "x-missing": "value" },
+            "body": [
+                { "path": "$.id", "equals": 8 },
+                { "path": "$.user", "matches": { "name": "other" } },
+                { "path": "$.user.email" },
+                { "path": "$.id", "exists": false }
+            ],
+            "schema": { "properties": { "id": { "type": "string" } } }
+        });
+
+        assert_eq!(
+            results(expect, &http_outcome()),
+            vec![
+                "FAIL status is 200-200: got 201 Created",
+                "FAIL header 'content-type' is 'text/plain': got 'application/json'",
+                "FAIL header 'x-missing' is 'value': missing",
+                "FAIL '$.id' equals 8: got 7",
+                "FAIL '$.user' matches {\"name\":\"other\"}: got {\"name\":\"me\",\"roles\":[\"admin\"]}",
+                "FAIL '$.user.email' exists: missing",
+                "FAIL '$.id' doesn't exist: got 7",
+                "FAIL body is valid against schema: $.id: expected type string, got number",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grpc() {
+        let outcome = Outcome::Grpc {
+            status: Status::new(Code::NotFound, "no such item"),
+            messages: vec![],
+        };
+
+        assert_eq!(
+            results(json!({ "status": "NOT_FOUND" }), &outcome),
+            vec!["PASS grpc status is NOT_FOUND"]
+        );
+        assert_eq!(
+            results(json!({}), &outcome),
+            vec!["FAIL grpc status is OK: got NOT_FOUND: no such item"]
+        );
+
+        let outcome = Outcome::Grpc {
+            status: Status::new(Code::Ok, ""),
+            messages: vec![json!({ "message": "hello" })],
+        };
+
+        assert!(results(json!({}), &outcome).is_empty());
+        assert_eq!(
+            results(
+                json!({ "status": 0, "body": [{ "path": "$.message", "equals": "hello" }] }),
+                &outcome
+            ),
+            vec![
+                "PASS grpc status is OK",
+                "PASS '$.message' equals \"hello\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_websocket() {
+        let outcome = Outcome::Websocket {
+            messages: vec![
+                ReceivedMessage {
+                    payload: json!({ "type": "welcome" }),
+                    elapsed: Duration::from_millis(100),
+                },
+                ReceivedMessage {
+                    payload: json!({ "type": "update", "price": 1 }),
+                    elapsed: Duration::from_secs(3),
+                },
+            ],
+            steps: vec![],
+        };
+
+        assert_eq!(
+            results(
+                json!({ "message": { "matches": { "type": "update" } } }),
+                &outcome
+            ),
+            vec!["PASS message matching {\"type\":\"update\"}"]
+        );
+        assert_eq!(
+            results(
+                json!({ "message": { "matches": { "type": "update" }, "within": 1 } }),
+                &outcome
+            ),
+            vec!["FAIL message matching {\"type\":\"update\"} within 1s: none of 2 received messages"]
+        );
+    }
+
+    #[test]
+    fn test_websocket_steps() {
+        let outcome = Outcome::Websocket {
+            messages: vec![],
+            steps: vec![
+                Assertion::new("step 1: send {\"type\":\"init\"}".to_owned(), None),
+                Assertion::new(
+                    "step 2: expect message matching {\"type\":\"ack\"}".to_owned(),
+                    Some("connection closed after 0 of 1 matching messages".to_owned()),
+                ),
+            ],
+        };
+
+        assert_eq!(
+            results(json!({}), &outcome),
+            vec![
+                "PASS step 1: send {\"type\":\"init\"}",
+                "FAIL step 2: expect message matching {\"type\":\"ack\"}: connection closed after 0 of 1 matching messages"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_expectations() {
+        let config = json!({ "client": "http", "expect": { "status": "abc" } });
+        let err = check(&config.to_string(), &http_outcome()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Invalid expectations: Invalid http status 'abc'"
+        );
+
+        let config = json!({ "client": "http", "expect": { "message": { "matches": {} } } });
+        let err = check(&config.to_string(), &http_outcome()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Invalid expectations: Messages can only be expected for websocket"
+        );
+
+        let config = json!({
+            "client": "websocket",
+            "expect": { "message": { "matches": {}, "within": -1 } }
+        });
+        let err = check(&config.to_string(), &http_outcome()).unwrap_err();
+        assert!(
+            format!("{:#}", err).starts_with("Can't deserialize expectations: invalid seconds"),
+            "{:#}",
+            err
+        );
+
+        // Schemas with unsupported keywords aren't passed without checking them
+        for (schema, keyword) in [
+            (
+                json!({ "properties": { "name": { "pattern": "^a" } } }),
+                "'pattern' at '#/properties/name/pattern'",
+            ),
+            (
+                json!({ "oneOf": [{ "type": "object" }] }),
+                "'oneOf' at '#/oneOf'",
+            ),
+        ] {
+            let config = json!({ "client": "http", "expect": { "schema": schema } });
+            let err = check(&config.to_string(), &http_outcome()).unwrap_err();
+            assert!(
+                format!("{:#}", err).starts_with(&format!(
+                    "Can't deserialize expectations: Unsupported json schema keyword {}",
+                    keyword
+                )),
+                "{:#}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(is_match(
+            &json!({ "a": 1, "b": { "c": 2, "d": 3 } }),
+            &json!({ "b": { "c": 2 } })
+        ));
+        assert!(!is_match(&json!({ "a": 1 }), &json!({ "b": 1 })));
+        assert!(!is_match(&json!([1, 2]), &json!([1])));
+        assert!(is_match(&json!("text"), &json!("text")));
+    }
+}
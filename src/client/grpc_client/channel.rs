@@ -0,0 +1,163 @@
+use crate::client::grpc_client::config::Tls;
+use anyhow::{anyhow, Context};
+use http::{uri::Scheme, Uri};
+use log::info;
+use std::fs;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+
+/// Builds the endpoint for the grpc channel.
+///
+/// Urls with scheme `https` use TLS with the native root certificates unless
+/// configured otherwise. If `tls.plaintext` is set, the connection uses
+/// HTTP/2 without TLS (h2c) regardless of the scheme.
+pub fn build_endpoint(url: &str, tls: Option<&Tls>) -> Result<Endpoint, anyhow::Error> {
+    let uri: Uri = url.parse().context("Can't parse url")?;
+
+    let tls = match tls {
+        Some(tls) if tls.plaintext => {
+            info!("Using plaintext connection");
+
+            let mut parts = uri.into_parts();
+            parts.scheme = Some(Scheme::HTTP);
+            let uri = Uri::from_parts(parts).context("Can't parse url")?;
+
+            return Ok(Channel::builder(uri));
+        }
+        Some(tls) => tls,
+        None => return Ok(Channel::builder(uri)),
+    };
+
+    let mut tls_config = ClientTlsConfig::new();
+
+    if let Some(ca_certificate) = &tls.ca_certificate {
+        info!("CA certificate: {}", ca_certificate);
+
+        let pem = fs::read(ca_certificate)
+            .context(format!("Can't read CA certificate '{}'", ca_certificate))?;
+        tls_config = tls_config.ca_certificate(Certificate::from_pem(pem));
+    }
+
+    match (&tls.client_certificate, &tls.client_key) {
+        (Some(client_certificate), Some(client_key)) => {
+            info!("Client certificate: {}", client_certificate);
+
+            let certificate = fs::read(client_certificate).context(format!(
+                "Can't read client certificate '{}'",
+                client_certificate
+            ))?;
+            let key =
+                fs::read(client_key).context(format!("Can't read client key '{}'", client_key))?;
+            tls_config = tls_config.identity(Identity::from_pem(certificate, key));
+        }
+        (None, None) => (),
+        _ => {
+            return Err(anyhow!(
+                "Both 'client_certificate' and 'client_key' are required for client authentication"
+            ))
+        }
+    }
+
+    if let Some(domain_name) = &tls.domain_name {
+        info!("Domain name: {}", domain_name);
+
+        tls_config = tls_config.domain_name(domain_name);
+    }
+
+    Channel::builder(uri)
+        .tls_config(tls_config)
+        .context("Can't configure tls")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::grpc_client::{send_request, test_server};
+    use prost_reflect::DynamicMessage;
+    use tonic::metadata::MetadataMap;
+
+    const TLS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls");
+
+    fn tls(client_certificate: bool) -> Tls {
+        Tls {
+            ca_certificate: Some(format!("{}/ca.pem", TLS_PATH)),
+            client_certificate: client_certificate.then(|| format!("{}/client.pem", TLS_PATH)),
+            client_key: client_certificate.then(|| format!("{}/client.key", TLS_PATH)),
+            domain_name: Some("localhost".to_string()),
+            plaintext: false,
+        }
+    }
+
+    async fn unary_echo(channel: Channel) -> Result<(), anyhow::Error> {
+        let method_descriptor = test_server::descriptor_pool()
+            .get_service_by_name("echo.Echo")
+            .unwrap()
+            .methods()
+            .find(|method_descriptor| method_descriptor.name() == "UnaryEcho")
+            .unwrap();
+        let message = DynamicMessage::new(method_descriptor.input());
+
+        send_request(
+            channel,
+            method_descriptor,
+            vec![message],
+            MetadataMap::new(),
+            None,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let addr = test_server::spawn_tls(false).await;
+        let url = format!("https://{}", addr);
+
+        let endpoint = build_endpoint(&url, Some(&tls(false))).unwrap();
+
+        assert!(endpoint.connect().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tls_client_certificate() {
+        let addr = test_server::spawn_tls(true).await;
+        let url = format!("https://{}", addr);
+
+        let endpoint = build_endpoint(&url, Some(&tls(true))).unwrap();
+        assert!(unary_echo(endpoint.connect().await.unwrap()).await.is_ok());
+
+        let endpoint = build_endpoint(&url, Some(&tls(false))).unwrap();
+        assert!(match endpoint.connect().await {
+            Ok(channel) => unary_echo(channel).await.is_err(),
+            Err(_) => true,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_tls_unknown_ca() {
+        let addr = test_server::spawn_tls(false).await;
+        let url = format!("https://{}", addr);
+        let tls = Tls {
+            ca_certificate: None,
+            ..tls(false)
+        };
+
+        let endpoint = build_endpoint(&url, Some(&tls)).unwrap();
+
+        assert!(endpoint.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plaintext() {
+        let addr = test_server::serve(None).await;
+        let url = format!("https://{}", addr);
+        let tls = Tls {
+            plaintext: true,
+            ..tls(false)
+        };
+
+        let endpoint = build_endpoint(&url, Some(&tls)).unwrap();
+
+        assert_eq!(endpoint.uri().scheme_str(), Some("http"));
+        assert!(endpoint.connect().await.is_ok());
+    }
+}
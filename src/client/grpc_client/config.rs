@@ -1,25 +1,59 @@
+use crate::client::timeout::Timeout;
 use serde::Deserialize;
 use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf};
 
 #[derive(Deserialize)]
 pub struct Config {
     pub api: Api,
     pub proto: Proto,
+    #[serde(default)]
+    pub timeout: Timeout,
 }
 
 #[derive(Deserialize)]
 pub struct Api {
     pub url: String,
+    #[serde(default)]
+    pub reflection: bool,
+    pub tls: Option<Tls>,
+}
+
+#[derive(Deserialize)]
+pub struct Tls {
+    pub ca_certificate: Option<String>,
+    pub client_certificate: Option<String>,
+    pub client_key: Option<String>,
+    pub domain_name: Option<String>,
+    #[serde(default)]
+    pub plaintext: bool,
 }
 
 #[derive(Deserialize)]
 pub struct Proto {
-    pub path: String,
-    pub file: String,
-    pub service: String,
-    pub package: String,
+    pub path: Option<Paths>,
+    pub file: Option<Paths>,
+    pub descriptor_set: Option<String>,
+    pub service: Option<String>,
+    pub package: Option<String>,
     pub method: String,
-    pub message: String,
     pub request: Option<Value>,
-    pub metadata: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// A single path or a list of paths.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Paths {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Paths {
+    pub fn to_path_bufs(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Single(path) => vec![PathBuf::from(path)],
+            Self::Multiple(paths) => paths.iter().map(PathBuf::from).collect(),
+        }
+    }
 }
@@ -0,0 +1,262 @@
+use crate::client::grpc_client::convert_descriptors::convert_file_descriptor_proto;
+use anyhow::Context;
+use prost::Message;
+use prost_reflect::DescriptorPool;
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use protobuf::well_known_types::{
+    any, api, duration, empty, field_mask, source_context, struct_, timestamp, type_, wrappers,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Builds the descriptor pool from the given proto files and all their
+/// transitive imports.
+pub fn from_proto_files(
+    proto_paths: &[PathBuf],
+    proto_files: &[PathBuf],
+) -> Result<DescriptorPool, anyhow::Error> {
+    let file_descriptor_protos = parse_file_descriptor_protos(proto_paths, proto_files)?;
+    from_file_descriptor_protos(file_descriptor_protos)
+}
+
+/// Builds the descriptor pool from a binary `FileDescriptorSet`, e.g. as
+/// generated by `protoc --descriptor_set_out`.
+pub fn from_descriptor_set_file(path: &Path) -> Result<DescriptorPool, anyhow::Error> {
+    let bytes = fs::read(path).context(format!("Can't read file '{}'", path.display()))?;
+    let file_descriptor_set = FileDescriptorSet::decode(bytes.as_slice())
+        .context(format!("Can't decode descriptor set '{}'", path.display()))?;
+    from_file_descriptor_protos(file_descriptor_set.file)
+}
+
+/// Builds the descriptor pool from the given file descriptors. The
+/// well-known types (`google/protobuf/*.proto`) are added if missing.
+pub fn from_file_descriptor_protos(
+    mut file_descriptor_protos: Vec<FileDescriptorProto>,
+) -> Result<DescriptorPool, anyhow::Error> {
+    let names = file_descriptor_protos
+        .iter()
+        .map(|file_descriptor_proto| file_descriptor_proto.name().to_owned())
+        .collect::<HashSet<_>>();
+
+    for well_known_type in well_known_types() {
+        if !names.contains(well_known_type.name()) {
+            file_descriptor_protos.push(well_known_type);
+        }
+    }
+
+    let mut pool = DescriptorPool::new();
+
+    for file_descriptor_proto in sort_by_dependencies(file_descriptor_protos) {
+        if pool
+            .files()
+            .any(|file| file.name() == file_descriptor_proto.name())
+        {
+            continue;
+        }
+
+        let name = file_descriptor_proto.name().to_owned();
+        pool.add_file_descriptor_proto(file_descriptor_proto)
+            .context(format!("Can't add file descriptor '{}'", name))?;
+    }
+
+    Ok(pool)
+}
+
+/// Parses the given proto files. The returned file descriptors include all
+/// transitive imports in dependency order.
+pub fn parse_file_descriptor_protos(
+    proto_paths: &[PathBuf],
+    proto_files: &[PathBuf],
+) -> Result<Vec<FileDescriptorProto>, anyhow::Error> {
+    let proto_files = proto_files
+        .iter()
+        .map(|proto_file| resolve_proto_file(proto_paths, proto_file))
+        .collect::<Vec<_>>();
+
+    let file_descriptor_protos = protobuf_parse::Parser::new()
+        .pure()
+        .includes(proto_paths)
+        .inputs(&proto_files)
+        .parse_and_typecheck()
+        .context(format!("Can't parse proto files {:?}", proto_files))?
+        .file_descriptors;
+
+    Ok(file_descriptor_protos
+        .into_iter()
+        .map(convert_file_descriptor_proto)
+        .collect())
+}
+
+/// Resolves a proto file relative to the proto paths if it doesn't reside in
+/// one of them already.
+fn resolve_proto_file(proto_paths: &[PathBuf], proto_file: &Path) -> PathBuf {
+    if proto_file.exists()
+        && proto_paths
+            .iter()
+            .any(|proto_path| proto_file.starts_with(proto_path))
+    {
+        return proto_file.to_owned();
+    }
+
+    proto_paths
+        .iter()
+        .map(|proto_path| proto_path.join(proto_file))
+        .find(|proto_file| proto_file.exists())
+        .unwrap_or_else(|| proto_file.to_owned())
+}
+
+/// Sorts the file descriptors so that each file comes after its
+/// dependencies.
+fn sort_by_dependencies(
+    file_descriptor_protos: Vec<FileDescriptorProto>,
+) -> Vec<FileDescriptorProto> {
+    fn visit(
+        name: &str,
+        unsorted: &mut HashMap<String, FileDescriptorProto>,
+        visited: &mut HashSet<String>,
+        sorted: &mut Vec<FileDescriptorProto>,
+    ) {
+        if !visited.insert(name.to_owned()) {
+            return;
+        }
+
+        if let Some(file_descriptor_proto) = unsorted.remove(name) {
+            for dependency in &file_descriptor_proto.dependency {
+                visit(dependency, unsorted, visited, sorted);
+            }
+
+            sorted.push(file_descriptor_proto);
+        }
+    }
+
+    let names = file_descriptor_protos
+        .iter()
+        .map(|file_descriptor_proto| file_descriptor_proto.name().to_owned())
+        .collect::<Vec<_>>();
+    let mut unsorted = file_descriptor_protos
+        .into_iter()
+        .map(|file_descriptor_proto| {
+            (
+                file_descriptor_proto.name().to_owned(),
+                file_descriptor_proto,
+            )
+        })
+        .collect();
+    let mut visited = HashSet::new();
+    let mut sorted = vec![];
+
+    for name in names {
+        visit(&name, &mut unsorted, &mut visited, &mut sorted);
+    }
+
+    sorted
+}
+
+fn well_known_types() -> Vec<FileDescriptorProto> {
+    [
+        any::file_descriptor(),
+        api::file_descriptor(),
+        protobuf::descriptor::file_descriptor(),
+        duration::file_descriptor(),
+        empty::file_descriptor(),
+        field_mask::file_descriptor(),
+        source_context::file_descriptor(),
+        struct_::file_descriptor(),
+        timestamp::file_descriptor(),
+        type_::file_descriptor(),
+        wrappers::file_descriptor(),
+    ]
+    .into_iter()
+    .map(|file_descriptor| convert_file_descriptor_proto(file_descriptor.proto().clone()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::DynamicMessage;
+    use serde_json::json;
+
+    const PROTO_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto");
+
+    #[test]
+    fn test_from_proto_files() {
+        let pool = from_proto_files(
+            &[PathBuf::from(PROTO_PATH)],
+            &[
+                PathBuf::from("event/event.proto"),
+                PathBuf::from("echo.proto"),
+            ],
+        )
+        .unwrap();
+
+        assert!(pool.get_message_by_name("event.Event").is_some());
+        assert!(pool.get_message_by_name("common.Tag").is_some());
+        assert!(pool.get_message_by_name("echo.EchoMessage").is_some());
+        assert!(pool
+            .get_message_by_name("google.protobuf.Timestamp")
+            .is_some());
+    }
+
+    #[test]
+    fn test_well_known_types() {
+        let pool = from_proto_files(
+            &[PathBuf::from(PROTO_PATH)],
+            &[PathBuf::from("event/event.proto")],
+        )
+        .unwrap();
+        let message_descriptor = pool.get_message_by_name("event.Event").unwrap();
+        let event = json!({
+            "id": "my_id",
+            "timestamp": "2022-09-01T12:00:00Z",
+            "tags": [{ "name": "my_tag", "createdAt": "2022-08-01T12:00:00Z" }],
+            "payload": {
+                "@type": "type.googleapis.com/common.Tag",
+                "name": "my_payload",
+                "createdAt": "2022-07-01T12:00:00Z"
+            }
+        });
+
+        let message = DynamicMessage::deserialize(message_descriptor, event.clone()).unwrap();
+
+        assert_eq!(serde_json::to_value(message).unwrap(), event);
+    }
+
+    #[test]
+    fn test_from_descriptor_set_file() {
+        let file_descriptor_set = FileDescriptorSet {
+            file: parse_file_descriptor_protos(
+                &[PathBuf::from(PROTO_PATH)],
+                &[PathBuf::from("event/event.proto")],
+            )
+            .unwrap(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("descriptor_set.pb");
+        fs::write(&path, file_descriptor_set.encode_to_vec()).unwrap();
+
+        let pool = from_descriptor_set_file(&path).unwrap();
+
+        assert!(pool.get_service_by_name("event.EventService").is_some());
+        assert!(pool.get_message_by_name("common.Tag").is_some());
+    }
+
+    #[test]
+    fn test_from_file_descriptor_protos_adds_well_known_types() {
+        let file_descriptor_protos = parse_file_descriptor_protos(
+            &[PathBuf::from(PROTO_PATH)],
+            &[PathBuf::from("common/tag.proto")],
+        )
+        .unwrap()
+        .into_iter()
+        .filter(|file_descriptor_proto| file_descriptor_proto.name() == "common/tag.proto")
+        .collect();
+
+        let pool = from_file_descriptor_protos(file_descriptor_protos).unwrap();
+
+        assert!(pool.get_message_by_name("common.Tag").is_some());
+    }
+}
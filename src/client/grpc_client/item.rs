@@ -0,0 +1,49 @@
+use crate::client::output::{Direction, Record};
+use http::HeaderMap;
+use log::error;
+use prost_reflect::DynamicMessage;
+use std::fmt;
+
+/// An item received from a grpc response.
+///
+/// For unary and client streaming methods, the trailers are merged into the
+/// headers.
+pub enum Item {
+    Headers(HeaderMap),
+    Message(DynamicMessage),
+    Trailers(HeaderMap),
+}
+
+impl Item {
+    pub fn to_record(&self) -> Record {
+        match self {
+            Self::Headers(headers) => {
+                Record::new("grpc", Direction::Received, "headers").headers(headers)
+            }
+            Self::Message(message) => {
+                let record = Record::new("grpc", Direction::Received, "message");
+
+                match serde_json::to_value(message) {
+                    Ok(body) => record.body(body),
+                    Err(err) => {
+                        error!("Can't convert message to json: {}", err);
+                        record
+                    }
+                }
+            }
+            Self::Trailers(trailers) => {
+                Record::new("grpc", Direction::Received, "trailers").headers(trailers)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Headers(headers) => write!(f, "Headers: {:#?}", headers),
+            Self::Message(message) => write!(f, "Message: {}", message),
+            Self::Trailers(trailers) => write!(f, "Trailers: {:#?}", trailers),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::client::grpc_client::config::Proto;
+use anyhow::anyhow;
+use prost_reflect::{DescriptorPool, MethodDescriptor};
+use std::fmt;
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// The fully-qualified name of a grpc method, e.g. `my_package.MyService/MyMethod`.
+#[derive(Debug, PartialEq)]
+pub struct MethodName {
+    pub service: String,
+    pub method: String,
+}
+
+impl MethodName {
+    /// Parses the method name from the `proto` config. The method is either
+    /// given as `my_package.MyService/MyMethod` (or
+    /// `my_package.MyService.MyMethod`), or by the separate fields `package`,
+    /// `service`, and `method`.
+    pub fn from_config(proto: &Proto) -> Result<Self, anyhow::Error> {
+        if let Some((service, method)) = proto.method.rsplit_once('/') {
+            return Ok(Self::new(service, method));
+        }
+
+        match (&proto.package, &proto.service) {
+            (Some(package), Some(service)) if !package.is_empty() => Ok(Self::new(
+                &format!("{}.{}", package, service),
+                &proto.method,
+            )),
+            (_, Some(service)) => Ok(Self::new(service, &proto.method)),
+            (_, None) => match proto.method.rsplit_once('.') {
+                Some((service, method)) => Ok(Self::new(service, method)),
+                None => Err(anyhow!(
+                    "Invalid method '{}': expected format 'my_package.MyService/MyMethod'",
+                    proto.method
+                )),
+            },
+        }
+    }
+
+    fn new(service: &str, method: &str) -> Self {
+        Self {
+            service: service.trim_start_matches('.').to_owned(),
+            method: method.to_owned(),
+        }
+    }
+
+    /// Resolves the method in the descriptor pool by its fully-qualified
+    /// name.
+    pub fn resolve(&self, pool: &DescriptorPool) -> Result<MethodDescriptor, anyhow::Error> {
+        pool.get_service_by_name(&self.service)
+            .and_then(|service| {
+                service
+                    .methods()
+                    .find(|method| method.name() == self.method)
+            })
+            .ok_or_else(|| {
+                let suggestions = self.suggestions(pool);
+
+                if suggestions.is_empty() {
+                    anyhow!("Can't find method '{}'", self)
+                } else {
+                    anyhow!(
+                        "Can't find method '{}'. Did you mean one of these?\n  {}",
+                        self,
+                        suggestions.join("\n  ")
+                    )
+                }
+            })
+    }
+
+    /// Lists the methods in the descriptor pool with the closest names.
+    fn suggestions(&self, pool: &DescriptorPool) -> Vec<String> {
+        let name = self.to_string().to_lowercase();
+        let mut candidates = pool
+            .services()
+            .flat_map(|service| service.methods().collect::<Vec<_>>())
+            .map(|method| format!("{}/{}", method.parent_service().full_name(), method.name()))
+            .map(|candidate| (distance(&name, &candidate.to_lowercase()), candidate))
+            .filter(|(distance, candidate)| {
+                *distance <= name.len() / 2
+                    || candidate
+                        .to_lowercase()
+                        .ends_with(&format!("/{}", self.method.to_lowercase()))
+            })
+            .collect::<Vec<_>>();
+
+        candidates.sort();
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+}
+
+impl fmt::Display for MethodName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.service, self.method)
+    }
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::grpc_client::descriptor_pool;
+    use std::path::PathBuf;
+
+    const PROTO_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto");
+
+    fn proto(package: Option<&str>, service: Option<&str>, method: &str) -> Proto {
+        Proto {
+            path: None,
+            file: None,
+            descriptor_set: None,
+            package: package.map(ToOwned::to_owned),
+            service: service.map(ToOwned::to_owned),
+            method: method.to_owned(),
+            request: None,
+            metadata: None,
+        }
+    }
+
+    fn pool() -> DescriptorPool {
+        descriptor_pool::from_proto_files(
+            &[PathBuf::from(PROTO_PATH)],
+            &[PathBuf::from("echo.proto"), PathBuf::from("v2/echo.proto")],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_config() {
+        let expected = MethodName::new("echo.v2.Echo", "UnaryEcho");
+
+        let method_names = [
+            proto(None, None, "echo.v2.Echo/UnaryEcho"),
+            proto(None, None, "echo.v2.Echo.UnaryEcho"),
+            proto(Some("echo.v2"), Some("Echo"), "UnaryEcho"),
+            proto(None, Some("echo.v2.Echo"), "UnaryEcho"),
+        ];
+
+        for proto in method_names {
+            assert_eq!(MethodName::from_config(&proto).unwrap(), expected);
+        }
+
+        assert!(MethodName::from_config(&proto(None, None, "UnaryEcho")).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let pool = pool();
+
+        let method = MethodName::new("echo.Echo", "UnaryEcho")
+            .resolve(&pool)
+            .unwrap();
+        assert_eq!(method.parent_service().full_name(), "echo.Echo");
+        assert_eq!(method.input().full_name(), "echo.EchoMessage");
+
+        let method = MethodName::new("echo.v2.Echo", "UnaryEcho")
+            .resolve(&pool)
+            .unwrap();
+        assert_eq!(method.parent_service().full_name(), "echo.v2.Echo");
+        assert_eq!(method.input().full_name(), "echo.v2.EchoMessage");
+    }
+
+    #[test]
+    fn test_resolve_suggestions() {
+        let err = MethodName::new("echo.Echo", "UnaryEhco")
+            .resolve(&pool())
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("Did you mean"));
+        assert!(err.contains("echo.Echo/UnaryEcho"));
+        assert!(!err.contains("echo.Echo/BidirectionalStreamingEcho"));
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("abc", "abc"), 0);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
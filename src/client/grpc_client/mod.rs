@@ -1,77 +1,209 @@
+mod channel;
 mod codec;
 mod config;
 mod convert_descriptors;
+mod descriptor_pool;
+mod item;
+mod method;
+mod reflection;
+mod request;
+#[cfg(test)]
+mod test_server;
 
 use crate::client::grpc_client::{
-    codec::DynamicCodec, config::Config, convert_descriptors::convert_file_descriptor_proto,
+    codec::DynamicCodec,
+    config::Config,
+    item::Item,
+    method::MethodName,
+    request::{build_metadata, read_messages},
+};
+use crate::client::{
+    expect,
+    outcome::Outcome,
+    output::{self, Direction, Record},
+    timeout::{with_timeout, TimeoutError},
 };
 use anyhow::{anyhow, Context};
-use http::{uri::PathAndQuery, Uri};
+use futures::{stream, stream::BoxStream, StreamExt};
+use http::uri::PathAndQuery;
 use log::{error, info};
-use prost_reflect::{DescriptorPool, DynamicMessage};
-use serde_json::Deserializer;
-use std::{path::Path, str::FromStr};
-use tokio_stream::StreamExt;
-use tonic::{client::Grpc, transport::Channel, IntoRequest};
+use prost_reflect::{DynamicMessage, MethodDescriptor};
+use serde_json::Value;
+use std::{
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tonic::{
+    client::Grpc, metadata::MetadataMap, transport::Channel, Code, Request, Response, Status,
+    Streaming,
+};
 
-pub async fn use_grpc_client(config_file: String) -> Result<(), anyhow::Error> {
+/// Runs the grpc client. Response messages are only kept for the outcome with
+/// `keep_messages`, as streaming responses may run for a long time.
+pub async fn use_grpc_client(
+    config_file: String,
+    keep_messages: bool,
+) -> Result<Outcome, anyhow::Error> {
     info!("Using grpc client");
 
     let config: Config = serde_json::from_str(&config_file).context("Can't deserialize json")?;
-    let url = config.api.url;
-    let proto_path = Path::new(&config.proto.path);
-    let proto_file = Path::new(&config.proto.file);
-
-    info!("Proto path: {}", proto_path.display());
-    info!("Proto file: {}", proto_file.display());
-
-    let mut file_descriptor_protos = protobuf_parse::Parser::new()
-        .pure()
-        .includes(&[proto_path])
-        .input(proto_file)
-        .parse_and_typecheck()
-        .context(format!("Can't parse proto file '{}'", proto_file.display()))?
-        .file_descriptors;
-
-    let file_descriptor_proto = file_descriptor_protos
-        .pop()
-        .ok_or_else(|| anyhow!("Can't get proto"))?;
-
-    let file_descriptor_proto = convert_file_descriptor_proto(file_descriptor_proto);
-
-    let mut pool = DescriptorPool::new();
-    pool.add_file_descriptor_proto(file_descriptor_proto)
-        .context("Can't add file descriptor")?;
-
-    let message_descriptor = pool
-        .get_message_by_name(format!("{}.{}", config.proto.package, config.proto.message).as_str())
-        .context("Can't get message descriptor")?;
-
-    let service_descriptor = pool
-        .services()
-        .find(|service| service.name() == config.proto.service)
-        .context("Can't get service descriptor")?;
-
-    let method_descriptor = service_descriptor
-        .methods()
-        .find(|method| method.name() == config.proto.method)
-        .context("Can't get method descriptor")?;
-
-    let message = config
-        .proto
-        .request
-        .context("Can't get message")?
-        .to_string();
-    let mut deserializer = Deserializer::from_str(message.as_str());
-    let dynamic_message = DynamicMessage::deserialize(message_descriptor, &mut deserializer)
-        .context("Can't deserialize dynamic message")?;
-    deserializer.end().context("Can't end deserializer")?;
-
-    let uri: Uri = url.parse().context("Can't parse url")?;
-
-    let builder = Channel::builder(uri);
-    let channel = builder.connect().await.context("Can't create channel")?;
+    let run_timeout = config.timeout.run();
+
+    with_timeout(run_timeout, TimeoutError::Run, call(config, keep_messages)).await?
+}
+
+async fn call(config: Config, keep_messages: bool) -> Result<Outcome, anyhow::Error> {
+    let method_name = MethodName::from_config(&config.proto)?;
+
+    info!("Method: {}", method_name);
+
+    let endpoint = channel::build_endpoint(&config.api.url, config.api.tls.as_ref())?;
+    let channel = with_timeout(
+        config.timeout.connect(),
+        TimeoutError::Connect,
+        endpoint.connect(),
+    )
+    .await?
+    .context("Can't create channel")?;
+
+    let pool = if config.api.reflection {
+        info!("Using server reflection");
+
+        reflection::build_descriptor_pool(channel.clone(), &method_name.service).await?
+    } else if let Some(descriptor_set) = config.proto.descriptor_set {
+        info!("Descriptor set: {}", descriptor_set);
+
+        descriptor_pool::from_descriptor_set_file(Path::new(&descriptor_set))?
+    } else {
+        let proto_paths = config
+            .proto
+            .path
+            .context("Missing field 'proto.path'")?
+            .to_path_bufs();
+        let proto_files = config
+            .proto
+            .file
+            .context("Missing field 'proto.file'")?
+            .to_path_bufs();
+
+        info!("Proto paths: {:?}", proto_paths);
+        info!("Proto files: {:?}", proto_files);
+
+        descriptor_pool::from_proto_files(&proto_paths, &proto_files)?
+    };
+
+    let method_descriptor = method_name.resolve(&pool)?;
+
+    let messages = read_messages(config.proto.request, method_descriptor.input())?;
+    let metadata = build_metadata(config.proto.metadata)?;
+    let request_timeout = config.timeout.request();
+    let deadline = request_timeout.map(|timeout| Instant::now() + timeout);
+
+    if !output::is_text() {
+        let mut values = messages
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Can't convert message to json")?;
+        let body = match values.len() {
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        };
+
+        output::emit(
+            Record::new("grpc", Direction::Sent, "request")
+                .detail("method", method_name.to_string())
+                .headers(&metadata.clone().into_headers())
+                .body(body),
+        );
+    }
+
+    // The deadline is sent to the server, but is also enforced by the client
+    // for streaming responses
+    with_timeout(request_timeout, TimeoutError::Request, async {
+        let result = send_request(
+            channel,
+            method_descriptor,
+            messages,
+            metadata,
+            request_timeout,
+        )
+        .await;
+
+        // An error status is part of the outcome, so that it can be checked
+        // by expectations
+        let mut stream = match result {
+            Ok(stream) => stream,
+            Err(err) if err.downcast_ref::<TimeoutError>().is_some() => return Err(err),
+            Err(err) => match err.downcast_ref::<Status>() {
+                Some(status) => {
+                    error!("{:#}", err);
+                    output::emit(status_record(status));
+
+                    return Ok(Outcome::Grpc {
+                        status: status.to_owned(),
+                        messages: vec![],
+                    });
+                }
+                None => return Err(err),
+            },
+        };
+        let mut status = Status::new(Code::Ok, "");
+        let mut messages = vec![];
+
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(item) => {
+                    if let Item::Message(message) = &item {
+                        if keep_messages {
+                            messages.push(
+                                serde_json::to_value(message)
+                                    .context("Can't convert message to json")?,
+                            );
+                        }
+                    }
+
+                    if output::is_text() {
+                        println!("{}", item)
+                    } else {
+                        output::emit(item.to_record())
+                    }
+                }
+                Err(status) if is_deadline_exceeded(&status, deadline) => {
+                    return Err(status_error(status, request_timeout, deadline))
+                }
+                Err(err) => {
+                    error!("{}", err);
+                    status = err;
+                }
+            }
+        }
 
+        output::emit(status_record(&status));
+
+        Ok(Outcome::Grpc { status, messages })
+    })
+    .await?
+}
+
+fn status_record(status: &Status) -> Record {
+    Record::new("grpc", Direction::Received, "status")
+        .detail("code", expect::grpc_code_name(status.code()))
+        .detail("message", status.message())
+}
+
+/// Sends the request messages according to the kind of the method (unary,
+/// client streaming, server streaming, or bidirectional streaming), and
+/// returns the response headers, messages, and trailers as a stream.
+async fn send_request(
+    channel: Channel,
+    method_descriptor: MethodDescriptor,
+    mut messages: Vec<DynamicMessage>,
+    metadata: MetadataMap,
+    timeout: Option<Duration>,
+) -> Result<BoxStream<'static, Result<Item, Status>>, anyhow::Error> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
     let mut client = Grpc::new(channel);
 
     client.ready().await.context("Client not ready")?;
@@ -83,23 +215,381 @@ pub async fn use_grpc_client(config_file: String) -> Result<(), anyhow::Error> {
     ))
     .context("Can't parse endpoint")?;
 
+    let is_client_streaming = method_descriptor.is_client_streaming();
+    let is_server_streaming = method_descriptor.is_server_streaming();
+
+    if !is_client_streaming && messages.len() != 1 {
+        return Err(anyhow!(
+            "Expected exactly one message for method '{}', but got {}",
+            method_descriptor.name(),
+            messages.len()
+        ));
+    }
+
     let codec = DynamicCodec::new(method_descriptor);
 
-    let request = dynamic_message.into_request();
+    let stream = match (is_client_streaming, is_server_streaming) {
+        (false, false) => {
+            let request = build_request(messages.remove(0), metadata, timeout);
+            let response = client
+                .unary(request, path, codec)
+                .await
+                .map_err(|status| status_error(status, timeout, deadline))?;
+            unary_items(response)
+        }
+        (false, true) => {
+            let request = build_request(messages.remove(0), metadata, timeout);
+            let response = client
+                .server_streaming(request, path, codec)
+                .await
+                .map_err(|status| status_error(status, timeout, deadline))?;
+            streaming_items(response)
+        }
+        (true, false) => {
+            let request = build_request(stream::iter(messages), metadata, timeout);
+            let response = client
+                .client_streaming(request, path, codec)
+                .await
+                .map_err(|status| status_error(status, timeout, deadline))?;
+            unary_items(response)
+        }
+        (true, true) => {
+            let request = build_request(stream::iter(messages), metadata, timeout);
+            let response = client
+                .streaming(request, path, codec)
+                .await
+                .map_err(|status| status_error(status, timeout, deadline))?;
+            streaming_items(response)
+        }
+    };
+
+    Ok(stream)
+}
+
+/// Builds the request with metadata. The timeout is sent as deadline in
+/// header `grpc-timeout`.
+fn build_request<T>(message: T, metadata: MetadataMap, timeout: Option<Duration>) -> Request<T> {
+    let mut request = Request::new(message);
+    *request.metadata_mut() = metadata;
+
+    if let Some(timeout) = timeout {
+        request.set_timeout(timeout);
+    }
+
+    request
+}
+
+/// Checks if the status was caused by an expired deadline, either reported by
+/// the server or enforced by the client. The client cancels the call at the
+/// deadline, but a cancellation before the deadline comes from the server.
+fn is_deadline_exceeded(status: &Status, deadline: Option<Instant>) -> bool {
+    match (status.code(), deadline) {
+        (Code::DeadlineExceeded, Some(_)) => true,
+        (Code::Cancelled, Some(deadline)) => Instant::now() >= deadline,
+        _ => false,
+    }
+}
+
+fn status_error(
+    status: Status,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> anyhow::Error {
+    match timeout {
+        Some(timeout) if is_deadline_exceeded(&status, deadline) => {
+            anyhow::Error::new(status).context(TimeoutError::Request(timeout))
+        }
+        _ => anyhow::Error::new(status).context("Can't send grpc request"),
+    }
+}
+
+fn unary_items(response: Response<DynamicMessage>) -> BoxStream<'static, Result<Item, Status>> {
+    let headers = response.metadata().clone().into_headers();
+    let items = vec![
+        Ok(Item::Headers(headers)),
+        Ok(Item::Message(response.into_inner())),
+    ];
+    stream::iter(items).boxed()
+}
+
+fn streaming_items(
+    response: Response<Streaming<DynamicMessage>>,
+) -> BoxStream<'static, Result<Item, Status>> {
+    let headers = response.metadata().clone().into_headers();
+    let messages = stream::unfold(Some(response.into_inner()), |stream| async move {
+        let mut stream = stream?;
+
+        match stream.message().await {
+            Ok(Some(message)) => Some((Ok(Item::Message(message)), Some(stream))),
+            Ok(None) => match stream.trailers().await {
+                Ok(Some(trailers)) => Some((Ok(Item::Trailers(trailers.into_headers())), None)),
+                Ok(None) => None,
+                Err(status) => Some((Err(status), None)),
+            },
+            Err(status) => Some((Err(status), None)),
+        }
+    });
+
+    stream::once(async { Ok(Item::Headers(headers)) })
+        .chain(messages)
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::grpc_client::test_server;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    async fn send(
+        method: &str,
+        request: serde_json::Value,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Vec<Item> {
+        let pool = test_server::descriptor_pool();
+        let method_descriptor = pool
+            .get_service_by_name("echo.Echo")
+            .unwrap()
+            .methods()
+            .find(|method_descriptor| method_descriptor.name() == method)
+            .unwrap();
+        let messages = read_messages(Some(request), method_descriptor.input()).unwrap();
+        let metadata = build_metadata(metadata).unwrap();
+        let channel = test_server::spawn().await;
 
-    let response = client
-        .server_streaming(request, path, codec)
+        send_request(channel, method_descriptor, messages, metadata, None)
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await
+    }
+
+    fn texts(items: &[Item]) -> Vec<String> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Message(message) => Some(test_server::text(message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_unary() {
+        let items = send("UnaryEcho", json!({ "message": "hello" }), None).await;
+        assert_eq!(texts(&items), vec!["hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_server_streaming() {
+        let items = send("ServerStreamingEcho", json!({ "message": "hello" }), None).await;
+        assert_eq!(texts(&items), vec!["hello", "hello", "hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_client_streaming() {
+        let items = send(
+            "ClientStreamingEcho",
+            json!([{ "message": "hello" }, { "message": "world" }]),
+            None,
+        )
+        .await;
+        assert_eq!(texts(&items), vec!["hello world"]);
+    }
+
+    #[tokio::test]
+    async fn test_bidirectional_streaming() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bidirectional_streaming.ndjson");
+        std::fs::write(&path, "{\"message\": \"hello\"}\n\n{\"message\": \"world\"}\n").unwrap();
+
+        let items = send(
+            "BidirectionalStreamingEcho",
+            json!(path.display().to_string()),
+            None,
+        )
+        .await;
+        assert_eq!(texts(&items), vec!["hello", "world"]);
+    }
+
+    #[tokio::test]
+    async fn test_reflection() {
+        let channel = test_server::spawn().await;
+        let pool = reflection::build_descriptor_pool(channel.clone(), "echo.Echo")
+            .await
+            .unwrap();
+        let method_descriptor = pool
+            .get_service_by_name("echo.Echo")
+            .unwrap()
+            .methods()
+            .find(|method_descriptor| method_descriptor.name() == "UnaryEcho")
+            .unwrap();
+        let messages = read_messages(
+            Some(json!({ "message": "hello" })),
+            method_descriptor.input(),
+        )
+        .unwrap();
+
+        let items: Vec<Item> = send_request(
+            channel,
+            method_descriptor,
+            messages,
+            MetadataMap::new(),
+            None,
+        )
         .await
-        .context("Can't send grpc request")?;
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+        assert_eq!(texts(&items), vec!["hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_reflection_unknown_symbol() {
+        let channel = test_server::spawn().await;
+        let result = reflection::build_descriptor_pool(channel, "echo.Unknown").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metadata() {
+        let metadata = HashMap::from([
+            ("x-tenant".to_string(), "my_tenant".to_string()),
+            ("x-token-bin".to_string(), base64::encode("my_token")),
+        ]);
+        let items = send(
+            "ServerStreamingEcho",
+            json!({ "message": "hello" }),
+            Some(metadata),
+        )
+        .await;
 
-    let mut stream = response.into_inner();
+        match items.first() {
+            Some(Item::Headers(headers)) => {
+                assert_eq!(headers["x-tenant"], "my_tenant");
+                assert_eq!(
+                    headers["x-token-bin"],
+                    base64::encode("my_token").trim_end_matches('=')
+                );
+            }
+            _ => panic!("Missing headers"),
+        }
 
-    while let Some(item) = stream.next().await {
-        match item {
-            Ok(msg) => println!("{}", msg),
-            Err(err) => error!("{}", err),
+        match items.last() {
+            Some(Item::Trailers(trailers)) => assert_eq!(trailers["grpc-status"], "0"),
+            _ => panic!("Missing trailers"),
         }
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_deadline() {
+        let pool = test_server::descriptor_pool();
+        let method_descriptor = pool
+            .get_service_by_name("echo.Echo")
+            .unwrap()
+            .methods()
+            .find(|method_descriptor| method_descriptor.name() == "UnaryEcho")
+            .unwrap();
+        let messages = read_messages(
+            Some(json!({ "message": "hello" })),
+            method_descriptor.input(),
+        )
+        .unwrap();
+        let metadata = build_metadata(Some(HashMap::from([(
+            "x-delay-ms".to_string(),
+            "1000".to_string(),
+        )])))
+        .unwrap();
+        let timeout = Duration::from_millis(100);
+        let channel = test_server::spawn().await;
+
+        let err = send_request(
+            channel,
+            method_descriptor,
+            messages,
+            metadata,
+            Some(timeout),
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError::Request(timeout))
+        );
+    }
+
+    #[test]
+    fn test_is_deadline_exceeded() {
+        let elapsed = Some(Instant::now());
+        let pending = Some(Instant::now() + Duration::from_secs(60));
+
+        assert!(is_deadline_exceeded(
+            &Status::deadline_exceeded(""),
+            pending
+        ));
+        assert!(is_deadline_exceeded(&Status::cancelled(""), elapsed));
+        assert!(!is_deadline_exceeded(&Status::cancelled(""), pending));
+        assert!(!is_deadline_exceeded(&Status::cancelled(""), None));
+        assert!(!is_deadline_exceeded(&Status::unavailable(""), elapsed));
+    }
+
+    #[tokio::test]
+    async fn test_keep_messages() {
+        let addr = test_server::serve(None).await;
+        let config = json!({
+            "client": "grpc",
+            "api": { "url": format!("http://{}", addr) },
+            "proto": {
+                "path": concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto"),
+                "file": "echo.proto",
+                "method": "echo.Echo/ServerStreamingEcho",
+                "request": { "message": "hello" }
+            }
+        });
+
+        for (keep_messages, expected) in [(true, 3), (false, 0)] {
+            let outcome = use_grpc_client(config.to_string(), keep_messages)
+                .await
+                .unwrap();
+
+            match outcome {
+                Outcome::Grpc { status, messages } => {
+                    assert_eq!(status.code(), Code::Ok);
+                    assert_eq!(messages.len(), expected);
+                }
+                _ => panic!("Expected grpc outcome"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout() {
+        let addr = test_server::serve(None).await;
+        let config = json!({
+            "client": "grpc",
+            "api": { "url": format!("http://{}", addr) },
+            "proto": {
+                "path": concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto"),
+                "file": "echo.proto",
+                "method": "echo.Echo/UnaryEcho",
+                "request": { "message": "hello" },
+                "metadata": { "x-delay-ms": "1000" }
+            },
+            "timeout": { "request": 5, "run": 0.1 }
+        });
+
+        let err = use_grpc_client(config.to_string(), false)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError::Run(Duration::from_millis(100)))
+        );
+    }
 }
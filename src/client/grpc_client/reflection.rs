@@ -0,0 +1,208 @@
+//! Client for the grpc server reflection protocol (see
+//! https://github.com/grpc/grpc/blob/master/doc/server-reflection.md).
+
+use crate::client::grpc_client::descriptor_pool;
+use anyhow::{anyhow, Context};
+use futures::{future, stream};
+use http::uri::PathAndQuery;
+use log::info;
+use prost::Message;
+use prost_reflect::DescriptorPool;
+use prost_types::FileDescriptorProto;
+use std::{collections::HashMap, str::FromStr};
+use tonic::{client::Grpc, codec::ProstCodec, transport::Channel, Code, Request, Status};
+
+/// The reflection services in order of preference. Both versions share the
+/// same message types.
+const REFLECTION_SERVICES: [&str; 2] = [
+    "grpc.reflection.v1.ServerReflection",
+    "grpc.reflection.v1alpha.ServerReflection",
+];
+
+/// Builds the descriptor pool from the file descriptors containing `symbol`
+/// and their transitive dependencies, as reported by the server.
+pub async fn build_descriptor_pool(
+    channel: Channel,
+    symbol: &str,
+) -> Result<DescriptorPool, anyhow::Error> {
+    for service in REFLECTION_SERVICES {
+        info!("Requesting file descriptors from '{}'", service);
+
+        let file_descriptor_protos =
+            match fetch_file_descriptor_protos(channel.clone(), service, symbol).await {
+                Ok(file_descriptor_protos) => file_descriptor_protos,
+                Err(status) if status.code() == Code::Unimplemented => continue,
+                Err(status) => {
+                    return Err(status)
+                        .context(format!("Can't get file descriptor for '{}'", symbol))
+                }
+            };
+
+        return descriptor_pool::from_file_descriptor_protos(file_descriptor_protos);
+    }
+
+    Err(anyhow!("Server reflection is not supported by server"))
+}
+
+async fn fetch_file_descriptor_protos(
+    channel: Channel,
+    service: &str,
+    symbol: &str,
+) -> Result<Vec<FileDescriptorProto>, Status> {
+    let mut client = Grpc::new(channel);
+    let path = PathAndQuery::from_str(&format!("/{}/ServerReflectionInfo", service))
+        .map_err(|err| Status::internal(err.to_string()))?;
+
+    let mut file_descriptor_protos = HashMap::new();
+    let mut requests = vec![MessageRequest::FileContainingSymbol(symbol.to_owned())];
+
+    while let Some(request) = requests.pop() {
+        for file_descriptor_proto in reflect(&mut client, path.clone(), request).await? {
+            for dependency in &file_descriptor_proto.dependency {
+                if !file_descriptor_protos.contains_key(dependency) {
+                    requests.push(MessageRequest::FileByFilename(dependency.clone()));
+                }
+            }
+
+            file_descriptor_protos.insert(
+                file_descriptor_proto.name().to_owned(),
+                file_descriptor_proto,
+            );
+        }
+    }
+
+    Ok(file_descriptor_protos.into_values().collect())
+}
+
+async fn reflect(
+    client: &mut Grpc<Channel>,
+    path: PathAndQuery,
+    message_request: MessageRequest,
+) -> Result<Vec<FileDescriptorProto>, Status> {
+    client
+        .ready()
+        .await
+        .map_err(|err| Status::unavailable(err.to_string()))?;
+
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(message_request),
+    };
+    let request = Request::new(stream::once(future::ready(request)));
+
+    let mut stream = client
+        .streaming(request, path, ProstCodec::default())
+        .await?
+        .into_inner();
+
+    let response: ServerReflectionResponse = stream
+        .message()
+        .await?
+        .ok_or_else(|| Status::internal("Missing reflection response"))?;
+
+    match response.message_response {
+        Some(MessageResponse::FileDescriptorResponse(response)) => {
+            let mut file_descriptor_protos = vec![];
+
+            for bytes in response.file_descriptor_proto {
+                let file_descriptor_proto = FileDescriptorProto::decode(bytes.as_slice())
+                    .map_err(|err| Status::internal(err.to_string()))?;
+                file_descriptor_protos.push(file_descriptor_proto);
+            }
+
+            Ok(file_descriptor_protos)
+        }
+        Some(MessageResponse::ErrorResponse(response)) => Err(Status::new(
+            Code::from(response.error_code),
+            response.error_message,
+        )),
+        _ => Err(Status::internal("Unexpected reflection response")),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ServerReflectionRequest {
+    #[prost(string, tag = "1")]
+    pub host: String,
+    #[prost(oneof = "MessageRequest", tags = "3, 4, 5, 6, 7")]
+    pub message_request: Option<MessageRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum MessageRequest {
+    #[prost(string, tag = "3")]
+    FileByFilename(String),
+    #[prost(string, tag = "4")]
+    FileContainingSymbol(String),
+    #[prost(message, tag = "5")]
+    FileContainingExtension(ExtensionRequest),
+    #[prost(string, tag = "6")]
+    AllExtensionNumbersOfType(String),
+    #[prost(string, tag = "7")]
+    ListServices(String),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ExtensionRequest {
+    #[prost(string, tag = "1")]
+    pub containing_type: String,
+    #[prost(int32, tag = "2")]
+    pub extension_number: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ServerReflectionResponse {
+    #[prost(string, tag = "1")]
+    pub valid_host: String,
+    #[prost(message, optional, tag = "2")]
+    pub original_request: Option<ServerReflectionRequest>,
+    #[prost(oneof = "MessageResponse", tags = "4, 5, 6, 7")]
+    pub message_response: Option<MessageResponse>,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum MessageResponse {
+    #[prost(message, tag = "4")]
+    FileDescriptorResponse(FileDescriptorResponse),
+    #[prost(message, tag = "5")]
+    AllExtensionNumbersResponse(ExtensionNumberResponse),
+    #[prost(message, tag = "6")]
+    ListServicesResponse(ListServiceResponse),
+    #[prost(message, tag = "7")]
+    ErrorResponse(ErrorResponse),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FileDescriptorResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub file_descriptor_proto: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ExtensionNumberResponse {
+    #[prost(string, tag = "1")]
+    pub base_type_name: String,
+    #[prost(int32, repeated, tag = "2")]
+    pub extension_number: Vec<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ListServiceResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub service: Vec<ServiceResponse>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ServiceResponse {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ErrorResponse {
+    #[prost(int32, tag = "1")]
+    pub error_code: i32,
+    #[prost(string, tag = "2")]
+    pub error_message: String,
+}
@@ -0,0 +1,70 @@
+use anyhow::Context;
+use prost_reflect::{DynamicMessage, MessageDescriptor};
+use serde_json::Value;
+use std::{collections::HashMap, fs, str::FromStr};
+use tonic::metadata::{
+    AsciiMetadataKey, AsciiMetadataValue, BinaryMetadataKey, BinaryMetadataValue, MetadataMap,
+};
+
+/// Reads the request messages from the `proto.request` field.
+///
+/// The request is either a single message, an array of messages, or a path
+/// to a NDJSON file with one message per line.
+pub fn read_messages(
+    request: Option<Value>,
+    message_descriptor: MessageDescriptor,
+) -> Result<Vec<DynamicMessage>, anyhow::Error> {
+    let values = match request.context("Can't get message")? {
+        Value::Array(values) => values,
+        Value::String(path) => read_ndjson(&path)?,
+        value => vec![value],
+    };
+
+    values
+        .into_iter()
+        .map(|value| {
+            DynamicMessage::deserialize(message_descriptor.clone(), value)
+                .context("Can't deserialize dynamic message")
+        })
+        .collect()
+}
+
+fn read_ndjson(path: &str) -> Result<Vec<Value>, anyhow::Error> {
+    let content = fs::read_to_string(path).context(format!("Can't read file '{}'", path))?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context(format!("Can't parse line '{}'", line)))
+        .collect()
+}
+
+/// Builds the request metadata from the `proto.metadata` field.
+///
+/// Values of binary keys, i.e. keys with suffix `-bin`, are expected to be
+/// base64 encoded.
+pub fn build_metadata(
+    metadata: Option<HashMap<String, String>>,
+) -> Result<MetadataMap, anyhow::Error> {
+    let mut metadata_map = MetadataMap::new();
+
+    for (key, value) in metadata.unwrap_or_default() {
+        if key.ends_with("-bin") {
+            let bytes = base64::decode(&value).context(format!(
+                "Can't decode base64 value of metadata key '{}'",
+                key
+            ))?;
+            let key = BinaryMetadataKey::from_str(&key)
+                .context(format!("Invalid metadata key '{}'", key))?;
+            metadata_map.insert_bin(key, BinaryMetadataValue::from_bytes(&bytes));
+        } else {
+            let value = AsciiMetadataValue::from_str(&value)
+                .context(format!("Invalid value of metadata key '{}'", key))?;
+            let key = AsciiMetadataKey::from_str(&key)
+                .context(format!("Invalid metadata key '{}'", key))?;
+            metadata_map.insert(key, value);
+        }
+    }
+
+    Ok(metadata_map)
+}
@@ -0,0 +1,339 @@
+//! Echo server implementing `tests/proto/echo.proto` for testing the grpc
+//! client.
+
+use crate::client::grpc_client::{
+    codec::DynamicCodec,
+    descriptor_pool,
+    reflection::{
+        ErrorResponse, FileDescriptorResponse, MessageRequest, MessageResponse,
+        ServerReflectionRequest, ServerReflectionResponse,
+    },
+};
+use futures::{stream, stream::BoxStream, StreamExt, TryStreamExt};
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MethodDescriptor, ServiceDescriptor, Value};
+use prost_types::FileDescriptorProto;
+use std::{
+    convert::Infallible,
+    fs,
+    net::SocketAddr,
+    path::PathBuf,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    body::BoxBody,
+    codec::ProstCodec,
+    codegen::{http, BoxFuture, Service},
+    metadata::{KeyAndValueRef, MetadataMap},
+    server::{
+        ClientStreamingService, Grpc, NamedService, ServerStreamingService, StreamingService,
+        UnaryService,
+    },
+    transport::{Body, Certificate, Channel, Identity, Server, ServerTlsConfig},
+    Request, Response, Status, Streaming,
+};
+
+const PROTO_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto");
+const PROTO_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto/echo.proto");
+const TLS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls");
+const SERVICE: &str = "echo.Echo";
+const REFLECTION_SERVICE: &str = "grpc.reflection.v1alpha.ServerReflection";
+
+pub fn descriptor_pool() -> DescriptorPool {
+    descriptor_pool::from_file_descriptor_protos(file_descriptor_protos()).unwrap()
+}
+
+fn file_descriptor_protos() -> Vec<FileDescriptorProto> {
+    descriptor_pool::parse_file_descriptor_protos(
+        &[PathBuf::from(PROTO_PATH)],
+        &[PathBuf::from(PROTO_FILE)],
+    )
+    .unwrap()
+}
+
+/// Starts the echo server, together with a `v1alpha` reflection service, on
+/// a random local port and returns a channel connected to it.
+pub async fn spawn() -> Channel {
+    let addr = serve(None).await;
+
+    Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+/// Starts the echo server with the certificates in `tests/tls` on a random
+/// local port and returns its address. If `client_auth` is set, clients must
+/// present a certificate signed by the test CA.
+pub async fn spawn_tls(client_auth: bool) -> SocketAddr {
+    let certificate = fs::read(format!("{}/server.pem", TLS_PATH)).unwrap();
+    let key = fs::read(format!("{}/server.key", TLS_PATH)).unwrap();
+    let mut tls_config = ServerTlsConfig::new().identity(Identity::from_pem(certificate, key));
+
+    if client_auth {
+        let ca_certificate = fs::read(format!("{}/ca.pem", TLS_PATH)).unwrap();
+        tls_config = tls_config.client_ca_root(Certificate::from_pem(ca_certificate));
+    }
+
+    serve(Some(tls_config)).await
+}
+
+/// Starts the echo server on a random local port and returns its address.
+pub async fn serve(tls_config: Option<ServerTlsConfig>) -> SocketAddr {
+    let service_descriptor = descriptor_pool().get_service_by_name(SERVICE).unwrap();
+    let file_descriptor_protos = file_descriptor_protos();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let mut server = Server::builder();
+
+    if let Some(tls_config) = tls_config {
+        server = server.tls_config(tls_config).unwrap();
+    }
+
+    tokio::spawn(
+        server
+            .add_service(EchoServer(service_descriptor))
+            .add_service(ReflectionServer(file_descriptor_protos))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    addr
+}
+
+/// Creates a response which echoes the custom request metadata, i.e. keys
+/// with prefix `x-`, as response headers.
+fn respond<T>(request_metadata: &MetadataMap, message: T) -> Response<T> {
+    let mut response = Response::new(message);
+
+    for key_and_value in request_metadata.iter() {
+        match key_and_value {
+            KeyAndValueRef::Ascii(key, value) if key.as_str().starts_with("x-") => {
+                response.metadata_mut().insert(key.clone(), value.clone());
+            }
+            KeyAndValueRef::Binary(key, value) if key.as_str().starts_with("x-") => {
+                response
+                    .metadata_mut()
+                    .insert_bin(key.clone(), value.clone());
+            }
+            _ => (),
+        }
+    }
+
+    response
+}
+
+/// Gets the `message` field of an `EchoMessage`.
+pub fn text(message: &DynamicMessage) -> String {
+    message
+        .get_field_by_name("message")
+        .and_then(|value| value.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
+
+#[derive(Clone)]
+struct EchoServer(ServiceDescriptor);
+
+impl NamedService for EchoServer {
+    const NAME: &'static str = SERVICE;
+}
+
+impl Service<http::Request<Body>> for EchoServer {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let method_name = request.uri().path().rsplit('/').next().unwrap_or_default();
+        let method_descriptor = self.0.methods().find(|method| method.name() == method_name);
+
+        Box::pin(async move {
+            let method_descriptor = match method_descriptor {
+                Some(method_descriptor) => method_descriptor,
+                None => return Ok(Status::unimplemented("Unknown method").to_http()),
+            };
+            let echo = Echo(method_descriptor.clone());
+            let mut grpc = Grpc::new(DynamicCodec::new(method_descriptor.clone()));
+
+            let response = match (
+                method_descriptor.is_client_streaming(),
+                method_descriptor.is_server_streaming(),
+            ) {
+                (false, false) => grpc.unary(echo, request).await,
+                (false, true) => grpc.server_streaming(echo, request).await,
+                (true, false) => grpc.client_streaming(echo, request).await,
+                (true, true) => grpc.streaming(echo, request).await,
+            };
+
+            Ok(response)
+        })
+    }
+}
+
+/// Echoes unary requests (delayed by the milliseconds given in metadata
+/// `x-delay-ms`), repeats server streaming requests three times,
+/// joins client streaming requests, and echoes each message of
+/// bidirectional streams.
+struct Echo(MethodDescriptor);
+
+impl Echo {
+    fn reply(&self, text: String) -> DynamicMessage {
+        let mut message = DynamicMessage::new(self.0.output());
+        message.set_field_by_name("message", Value::String(text));
+        message
+    }
+}
+
+impl UnaryService<DynamicMessage> for Echo {
+    type Response = DynamicMessage;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<DynamicMessage>) -> Self::Future {
+        let delay = request
+            .metadata()
+            .get("x-delay-ms")
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map(Duration::from_millis);
+        let response = respond(request.metadata(), self.reply(text(request.get_ref())));
+
+        Box::pin(async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+impl ServerStreamingService<DynamicMessage> for Echo {
+    type Response = DynamicMessage;
+    type ResponseStream = BoxStream<'static, Result<DynamicMessage, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<DynamicMessage>) -> Self::Future {
+        let reply = self.reply(text(request.get_ref()));
+        let response = respond(request.metadata(), stream::iter(vec![Ok(reply); 3]).boxed());
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+impl ClientStreamingService<DynamicMessage> for Echo {
+    type Response = DynamicMessage;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<DynamicMessage>>) -> Self::Future {
+        let echo = Echo(self.0.clone());
+
+        Box::pin(async move {
+            let metadata = request.metadata().clone();
+            let mut stream = request.into_inner();
+            let mut texts = vec![];
+
+            while let Some(message) = stream.next().await {
+                texts.push(text(&message?));
+            }
+
+            Ok(respond(&metadata, echo.reply(texts.join(" "))))
+        })
+    }
+}
+
+impl StreamingService<DynamicMessage> for Echo {
+    type Response = DynamicMessage;
+    type ResponseStream = BoxStream<'static, Result<DynamicMessage, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<DynamicMessage>>) -> Self::Future {
+        let echo = Echo(self.0.clone());
+        let metadata = request.metadata().clone();
+        let replies = request
+            .into_inner()
+            .map_ok(move |message| echo.reply(text(&message)))
+            .boxed();
+
+        Box::pin(async move { Ok(respond(&metadata, replies)) })
+    }
+}
+
+#[derive(Clone)]
+struct ReflectionServer(Vec<FileDescriptorProto>);
+
+impl NamedService for ReflectionServer {
+    const NAME: &'static str = REFLECTION_SERVICE;
+}
+
+impl Service<http::Request<Body>> for ReflectionServer {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let reflection = Reflection(self.0.clone());
+
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::default());
+            Ok(grpc.streaming(reflection, request).await)
+        })
+    }
+}
+
+/// Answers reflection requests by file name or by symbol.
+struct Reflection(Vec<FileDescriptorProto>);
+
+impl Reflection {
+    fn find(&self, message_request: Option<MessageRequest>) -> Option<&FileDescriptorProto> {
+        self.0.iter().find(|file| match &message_request {
+            Some(MessageRequest::FileByFilename(name)) => file.name() == name,
+            Some(MessageRequest::FileContainingSymbol(symbol)) => file
+                .service
+                .iter()
+                .any(|service| format!("{}.{}", file.package(), service.name()) == *symbol),
+            _ => false,
+        })
+    }
+}
+
+impl StreamingService<ServerReflectionRequest> for Reflection {
+    type Response = ServerReflectionResponse;
+    type ResponseStream = BoxStream<'static, Result<ServerReflectionResponse, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<ServerReflectionRequest>>) -> Self::Future {
+        let reflection = Reflection(self.0.clone());
+        let responses = request
+            .into_inner()
+            .map_ok(move |request| {
+                let message_response = match reflection.find(request.message_request.clone()) {
+                    Some(file) => MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+                        file_descriptor_proto: vec![file.encode_to_vec()],
+                    }),
+                    None => MessageResponse::ErrorResponse(ErrorResponse {
+                        error_code: tonic::Code::NotFound as i32,
+                        error_message: "File not found".to_string(),
+                    }),
+                };
+
+                ServerReflectionResponse {
+                    valid_host: request.host.clone(),
+                    original_request: Some(request),
+                    message_response: Some(message_response),
+                }
+            })
+            .boxed();
+
+        Box::pin(async move { Ok(Response::new(responses)) })
+    }
+}
@@ -0,0 +1,224 @@
+use crate::client::{
+    http_client::{
+        config::{BodyKind, File, Multipart},
+        request::{Body, FileInfo},
+    },
+    prettify_json,
+};
+use anyhow::{anyhow, Context};
+use reqwest::{
+    header::{HeaderValue, CONTENT_TYPE},
+    multipart::{Form, Part},
+    RequestBuilder,
+};
+use serde_json::Value;
+use std::path::Path;
+
+const TEXT_PLAIN: &str = "text/plain; charset=utf-8";
+const APPLICATION_OCTET_STREAM: &str = "application/octet-stream";
+
+/// Adds the body to the request, encoded according to the body kind, and
+/// returns the body for display.
+pub async fn add_body(
+    request: RequestBuilder,
+    body_kind: BodyKind,
+    body: Value,
+) -> Result<(RequestBuilder, Body), anyhow::Error> {
+    match body_kind {
+        BodyKind::Json => {
+            let request = request.json(&body);
+            Ok((request, Body::Json(prettify_json(body)?)))
+        }
+        BodyKind::Text => {
+            let text = match body {
+                Value::String(text) => text,
+                _ => return Err(anyhow!("Expected string for body kind 'text'")),
+            };
+            let request = request
+                .header(CONTENT_TYPE, HeaderValue::from_static(TEXT_PLAIN))
+                .body(text.clone());
+            Ok((request, Body::Text(text)))
+        }
+        BodyKind::Form => {
+            let fields = match body {
+                Value::Object(object) => to_fields(object),
+                _ => return Err(anyhow!("Expected object for body kind 'form'")),
+            };
+            let request = request.form(&fields);
+            Ok((request, Body::Form(fields)))
+        }
+        BodyKind::Multipart => {
+            let multipart: Multipart = serde_json::from_value(body)
+                .context("Can't deserialize body of kind 'multipart'")?;
+            let fields = to_fields(multipart.fields);
+            let mut form = Form::new();
+            let mut files = vec![];
+
+            for (key, value) in &fields {
+                form = form.text(key.clone(), value.clone());
+            }
+
+            for (key, file) in multipart.files {
+                let (part, file_info) = file_part(file).await?;
+                form = form.part(key.clone(), part);
+                files.push((key, file_info));
+            }
+
+            let request = request.multipart(form);
+            Ok((request, Body::Multipart { fields, files }))
+        }
+        BodyKind::File => {
+            let file: File =
+                serde_json::from_value(body).context("Can't deserialize body of kind 'file'")?;
+            let (reader, file_info) = open_file(file).await?;
+            let request = request
+                .header(
+                    CONTENT_TYPE,
+                    HeaderValue::from_str(&file_info.content_type)?,
+                )
+                .body(reader);
+            Ok((request, Body::File(file_info)))
+        }
+    }
+}
+
+/// Converts the values of the object to strings. Strings are taken without
+/// quotes, other values as json.
+fn to_fields(object: serde_json::Map<String, Value>) -> Vec<(String, String)> {
+    object
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => (key, value),
+            value => (key, value.to_string()),
+        })
+        .collect()
+}
+
+async fn file_part(file: File) -> Result<(Part, FileInfo), anyhow::Error> {
+    let (reader, file_info) = open_file(file).await?;
+    let file_name = Path::new(&file_info.path)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let part = Part::stream_with_length(reader, file_info.size)
+        .file_name(file_name)
+        .mime_str(&file_info.content_type)
+        .context(format!("Invalid content type '{}'", file_info.content_type))?;
+    Ok((part, file_info))
+}
+
+async fn open_file(file: File) -> Result<(reqwest::Body, FileInfo), anyhow::Error> {
+    let reader = tokio::fs::File::open(&file.path)
+        .await
+        .context(format!("Can't open file '{}'", file.path))?;
+    let size = reader
+        .metadata()
+        .await
+        .context(format!("Can't read metadata of file '{}'", file.path))?
+        .len();
+    let file_info = FileInfo {
+        path: file.path,
+        content_type: file
+            .content_type
+            .unwrap_or_else(|| APPLICATION_OCTET_STREAM.to_owned()),
+        size,
+    };
+    Ok((reqwest::Body::from(reader), file_info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Client, Request};
+    use serde_json::json;
+
+    const FILE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/proto/echo.proto");
+
+    async fn build(body_kind: BodyKind, body: Value) -> (Request, Body) {
+        let request = Client::new().post("http://localhost/my_endpoint");
+        let (request, body) = add_body(request, body_kind, body).await.unwrap();
+        (request.build().unwrap(), body)
+    }
+
+    fn content_type(request: &Request) -> &str {
+        request.headers()[CONTENT_TYPE].to_str().unwrap()
+    }
+
+    fn bytes(request: &Request) -> &[u8] {
+        request.body().unwrap().as_bytes().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json() {
+        let (request, body) = build(BodyKind::Json, json!({ "myKey": "my_value" })).await;
+
+        assert_eq!(content_type(&request), "application/json");
+        assert_eq!(bytes(&request), br#"{"myKey":"my_value"}"#);
+        assert!(matches!(body, Body::Json(_)));
+    }
+
+    #[tokio::test]
+    async fn test_text() {
+        let (request, body) = build(BodyKind::Text, json!("hello")).await;
+
+        assert_eq!(content_type(&request), TEXT_PLAIN);
+        assert_eq!(bytes(&request), b"hello");
+        assert!(matches!(body, Body::Text(text) if text == "hello"));
+    }
+
+    #[tokio::test]
+    async fn test_form() {
+        let (request, body) = build(BodyKind::Form, json!({ "a": "b c", "n": 1 })).await;
+
+        assert_eq!(content_type(&request), "application/x-www-form-urlencoded");
+        assert_eq!(bytes(&request), b"a=b+c&n=1");
+        assert!(matches!(body, Body::Form(fields) if fields.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn test_multipart() {
+        let (request, body) = build(
+            BodyKind::Multipart,
+            json!({
+                "fields": { "name": "my_name" },
+                "files": { "upload": { "path": FILE_PATH, "content_type": "text/plain" } }
+            }),
+        )
+        .await;
+
+        assert!(content_type(&request).starts_with("multipart/form-data; boundary="));
+
+        match body {
+            Body::Multipart { fields, files } => {
+                assert_eq!(fields, vec![("name".to_string(), "my_name".to_string())]);
+                assert_eq!(files[0].0, "upload");
+                assert_eq!(files[0].1.content_type, "text/plain");
+            }
+            _ => panic!("Expected multipart body"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file() {
+        let (request, body) = build(BodyKind::File, json!({ "path": FILE_PATH })).await;
+
+        assert_eq!(content_type(&request), APPLICATION_OCTET_STREAM);
+
+        match body {
+            Body::File(file_info) => {
+                assert_eq!(file_info.size, std::fs::metadata(FILE_PATH).unwrap().len());
+            }
+            _ => panic!("Expected file body"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_body() {
+        let request = Client::new().post("http://localhost/my_endpoint");
+        assert!(add_body(request, BodyKind::Text, json!({})).await.is_err());
+
+        let request = Client::new().post("http://localhost/my_endpoint");
+        let body = json!({ "path": "missing_file" });
+        assert!(add_body(request, BodyKind::File, body).await.is_err());
+    }
+}
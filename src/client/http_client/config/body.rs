@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// The kind of the request body, which determines how `request.body` is
+/// interpreted and encoded.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyKind {
+    /// Any json value
+    #[default]
+    Json,
+    /// A string sent as is
+    Text,
+    /// An object sent as `application/x-www-form-urlencoded`
+    Form,
+    /// Fields and files sent as `multipart/form-data`
+    Multipart,
+    /// A file streamed from disk
+    File,
+}
+
+#[derive(Deserialize)]
+pub struct Multipart {
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+    #[serde(default)]
+    pub files: BTreeMap<String, File>,
+}
+
+#[derive(Deserialize)]
+pub struct File {
+    pub path: String,
+    pub content_type: Option<String>,
+}
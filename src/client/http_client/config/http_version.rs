@@ -0,0 +1,51 @@
+use reqwest::Version;
+use serde::Deserialize;
+use std::fmt;
+
+const HTTP_11: &str = "http1.1";
+const HTTP_2: &str = "http2";
+const HTTP_2_PRIOR_KNOWLEDGE: &str = "http2-prior-knowledge";
+const AUTO: &str = "auto";
+
+/// The http version used for requests.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1 only
+    #[default]
+    #[serde(rename = "http1.1")]
+    Http11,
+    /// HTTP/2 negotiated via ALPN, which requires TLS
+    #[serde(rename = "http2")]
+    Http2,
+    /// HTTP/2 without negotiation, e.g. for cleartext HTTP/2 (h2c)
+    #[serde(rename = "http2-prior-knowledge")]
+    Http2PriorKnowledge,
+    /// HTTP/2 if negotiated via ALPN, HTTP/1.1 otherwise
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl HttpVersion {
+    /// The version required for the request, or `None` if any version is
+    /// accepted.
+    pub fn to_version(self) -> Option<Version> {
+        match self {
+            HttpVersion::Http11 => Some(Version::HTTP_11),
+            HttpVersion::Http2 | HttpVersion::Http2PriorKnowledge => Some(Version::HTTP_2),
+            HttpVersion::Auto => None,
+        }
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let http_version = match self {
+            HttpVersion::Http11 => HTTP_11,
+            HttpVersion::Http2 => HTTP_2,
+            HttpVersion::Http2PriorKnowledge => HTTP_2_PRIOR_KNOWLEDGE,
+            HttpVersion::Auto => AUTO,
+        };
+
+        write!(f, "{}", http_version)
+    }
+}
@@ -1,6 +1,13 @@
+mod body;
+mod http_version;
 mod request_method;
 
-pub(crate) use self::request_method::RequestMethod;
+pub(crate) use self::{
+    body::{BodyKind, File, Multipart},
+    http_version::HttpVersion,
+    request_method::RequestMethod,
+};
+use crate::client::{auth::Auth, timeout::Timeout};
 use serde::Deserialize;
 use serde_json::Value;
 
@@ -9,6 +16,14 @@ use serde_json::Value;
 pub struct Config {
     pub api: Api,
     pub request: Request,
+    #[serde(default)]
+    pub response: Response,
+    #[serde(default)]
+    pub redirect: Redirect,
+    pub cookies: Option<Cookies>,
+    pub auth: Option<Auth>,
+    #[serde(default)]
+    pub timeout: Timeout,
 }
 
 #[derive(Deserialize)]
@@ -16,16 +31,78 @@ pub struct Api {
     pub protocol: Option<String>,
     pub url: String,
     pub endpoint: String,
+    #[serde(default)]
+    pub version: HttpVersion,
 }
 
 #[derive(Deserialize)]
 pub struct Request {
     pub method: RequestMethod,
     pub headers: Option<Vec<Header>>,
+    #[serde(default)]
+    pub body_kind: BodyKind,
     pub body: Option<Value>,
     pub query_string: Option<Value>,
 }
 
+#[derive(Deserialize, Default)]
+pub struct Response {
+    /// Path of the file to save the raw response body to
+    pub save_body: Option<String>,
+    /// Maximum number of reconnections to an event stream
+    pub max_reconnects: Option<u32>,
+}
+
+/// Redirects are followed up to `max` times by default. Every redirect is
+/// printed as its own response.
+#[derive(Deserialize)]
+pub struct Redirect {
+    /// Whether to follow redirects at all
+    #[serde(default = "default_follow")]
+    pub follow: bool,
+    /// Maximum number of redirects to follow
+    #[serde(default = "default_max_redirects")]
+    pub max: usize,
+}
+
+impl Default for Redirect {
+    fn default() -> Self {
+        Self {
+            follow: default_follow(),
+            max: default_max_redirects(),
+        }
+    }
+}
+
+fn default_follow() -> bool {
+    true
+}
+
+fn default_max_redirects() -> usize {
+    10
+}
+
+/// The cookie store is enabled if this section is present, even without
+/// cookie file.
+#[derive(Deserialize)]
+pub struct Cookies {
+    /// Path of the cookie file, which is loaded if it exists and saved after
+    /// the run
+    pub file: Option<String>,
+    #[serde(default)]
+    pub format: CookieFormat,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieFormat {
+    /// The cookie file format of curl and browsers
+    #[default]
+    Netscape,
+    /// One json object per cookie and line
+    Json,
+}
+
 #[derive(Deserialize)]
 pub struct Header {
     pub key: String,
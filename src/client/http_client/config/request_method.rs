@@ -1,3 +1,4 @@
+use reqwest::Method;
 use serde::{de, Deserialize, Deserializer};
 use std::fmt;
 
@@ -5,12 +6,24 @@ const GET: &str = "GET";
 const POST: &str = "POST";
 const PUT: &str = "PUT";
 const DELETE: &str = "DELETE";
+const PATCH: &str = "PATCH";
+const HEAD: &str = "HEAD";
+const OPTIONS: &str = "OPTIONS";
+const CONNECT: &str = "CONNECT";
+const TRACE: &str = "TRACE";
 
 pub enum RequestMethod {
     Get,
     Post,
     Put,
     Delete,
+    Patch,
+    Head,
+    Options,
+    Connect,
+    Trace,
+    /// Any other valid method token, e.g. `PURGE`.
+    Extension(Method),
 }
 
 impl<'de> Deserialize<'de> for RequestMethod {
@@ -23,18 +36,43 @@ impl<'de> Deserialize<'de> for RequestMethod {
             POST => RequestMethod::Post,
             PUT => RequestMethod::Put,
             DELETE => RequestMethod::Delete,
-            others => {
-                return Err(de::Error::unknown_variant(
-                    others,
-                    &[GET, POST, PUT, DELETE],
-                ))
-            }
+            PATCH => RequestMethod::Patch,
+            HEAD => RequestMethod::Head,
+            OPTIONS => RequestMethod::Options,
+            CONNECT => RequestMethod::Connect,
+            TRACE => RequestMethod::Trace,
+            others => match Method::from_bytes(others.as_bytes()) {
+                Ok(method) => RequestMethod::Extension(method),
+                Err(_) => {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Str(others),
+                        &"a valid http method",
+                    ))
+                }
+            },
         };
 
         Ok(request_method)
     }
 }
 
+impl From<&RequestMethod> for Method {
+    fn from(request_method: &RequestMethod) -> Self {
+        match request_method {
+            RequestMethod::Get => Method::GET,
+            RequestMethod::Post => Method::POST,
+            RequestMethod::Put => Method::PUT,
+            RequestMethod::Delete => Method::DELETE,
+            RequestMethod::Patch => Method::PATCH,
+            RequestMethod::Head => Method::HEAD,
+            RequestMethod::Options => Method::OPTIONS,
+            RequestMethod::Connect => Method::CONNECT,
+            RequestMethod::Trace => Method::TRACE,
+            RequestMethod::Extension(method) => method.clone(),
+        }
+    }
+}
+
 impl fmt::Display for RequestMethod {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let request_method = match self {
@@ -42,8 +80,39 @@ impl fmt::Display for RequestMethod {
             RequestMethod::Post => POST,
             RequestMethod::Put => PUT,
             RequestMethod::Delete => DELETE,
+            RequestMethod::Patch => PATCH,
+            RequestMethod::Head => HEAD,
+            RequestMethod::Options => OPTIONS,
+            RequestMethod::Connect => CONNECT,
+            RequestMethod::Trace => TRACE,
+            RequestMethod::Extension(method) => method.as_str(),
         };
 
         write!(f, "{}", request_method)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize() {
+        for method in [
+            GET, POST, PUT, DELETE, PATCH, HEAD, OPTIONS, CONNECT, TRACE, "PURGE",
+        ] {
+            let request_method: RequestMethod =
+                serde_json::from_str(&format!("\"{}\"", method)).unwrap();
+
+            assert_eq!(request_method.to_string(), method);
+            assert_eq!(Method::from(&request_method).as_str(), method);
+        }
+
+        assert!(matches!(
+            serde_json::from_str("\"PURGE\""),
+            Ok(RequestMethod::Extension(_))
+        ));
+        assert!(serde_json::from_str::<RequestMethod>("\"NOT A METHOD\"").is_err());
+        assert!(serde_json::from_str::<RequestMethod>("\"\"").is_err());
+    }
+}
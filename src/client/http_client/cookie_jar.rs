@@ -0,0 +1,383 @@
+//! Cookie store which can be loaded from and saved to a cookie file, so that
+//! cookies of one run are sent in the next.
+
+use crate::client::http_client::config::{CookieFormat, Cookies};
+use anyhow::{anyhow, Context};
+use cookie_store::{Cookie, CookieDomain, CookieError, CookieExpiration, CookieStore};
+use log::{info, warn};
+use reqwest::{
+    header::{HeaderValue, COOKIE, SET_COOKIE},
+    Request, Response, Url,
+};
+use std::{fs, io::BufReader, path::Path};
+use time::OffsetDateTime;
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+pub struct CookieJar {
+    store: CookieStore,
+    file: Option<String>,
+    format: CookieFormat,
+}
+
+impl CookieJar {
+    /// Creates the cookie jar and loads the cookie file if it exists.
+    pub fn load(config: &Cookies) -> Result<Self, anyhow::Error> {
+        let store = match &config.file {
+            Some(file) if Path::new(file).exists() => {
+                info!("Loading cookies from '{}'", file);
+
+                let content =
+                    fs::read_to_string(file).context(format!("Can't read file '{}'", file))?;
+                let store = match config.format {
+                    CookieFormat::Netscape => from_netscape(&content),
+                    CookieFormat::Json => {
+                        CookieStore::load_json(BufReader::new(content.as_bytes()))
+                            .map_err(|err| anyhow!(err))
+                    }
+                };
+
+                store.context(format!("Can't load cookies from '{}'", file))?
+            }
+            _ => CookieStore::default(),
+        };
+
+        Ok(Self {
+            store,
+            file: config.file.to_owned(),
+            format: config.format,
+        })
+    }
+
+    /// Saves all unexpired cookies, including session cookies, to the cookie
+    /// file.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        info!("Saving cookies to '{}'", file);
+
+        let content = match self.format {
+            CookieFormat::Netscape => to_netscape(&self.store),
+            CookieFormat::Json => to_json(&self.store)?,
+        };
+
+        fs::write(file, content).context(format!("Can't write file '{}'", file))
+    }
+
+    /// Adds the stored cookies matching the request url to the `Cookie`
+    /// header. Cookies of a configured `Cookie` header are kept unless a
+    /// stored cookie has the same name.
+    pub fn add_cookie_header(&self, request: &mut Request) -> Result<(), anyhow::Error> {
+        let stored_cookies: Vec<(&str, &str)> =
+            self.store.get_request_values(request.url()).collect();
+
+        if stored_cookies.is_empty() {
+            return Ok(());
+        }
+
+        let mut cookies: Vec<String> = match request.headers().get(COOKIE) {
+            Some(header) => header
+                .to_str()
+                .context("Can't parse cookie header")?
+                .split(';')
+                .map(str::trim)
+                .filter(|cookie| {
+                    let name = cookie.split('=').next().unwrap_or_default();
+                    !cookie.is_empty() && stored_cookies.iter().all(|(stored, _)| *stored != name)
+                })
+                .map(ToOwned::to_owned)
+                .collect(),
+            None => vec![],
+        };
+
+        cookies.extend(
+            stored_cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value)),
+        );
+
+        request
+            .headers_mut()
+            .insert(COOKIE, HeaderValue::from_str(&cookies.join("; "))?);
+
+        Ok(())
+    }
+
+    /// Stores the cookies of the `Set-Cookie` headers.
+    pub fn store_response_cookies(&mut self, response: &Response) {
+        for header in response.headers().get_all(SET_COOKIE) {
+            let cookie = match header.to_str() {
+                Ok(cookie) => cookie,
+                Err(_) => {
+                    warn!("Can't store cookie {:?}", header);
+                    continue;
+                }
+            };
+
+            match self.store.parse(cookie, response.url()) {
+                // Expired cookies are ignored if they aren't stored yet
+                Ok(_) | Err(CookieError::Expired) => (),
+                Err(err) => warn!("Can't store cookie '{}': {}", cookie, err),
+            }
+        }
+    }
+}
+
+fn to_json(store: &CookieStore) -> Result<String, anyhow::Error> {
+    let mut content = String::new();
+
+    for cookie in store.iter_unexpired() {
+        content.push_str(&serde_json::to_string(cookie).context("Can't serialize cookie")?);
+        content.push('\n');
+    }
+
+    Ok(content)
+}
+
+/// Converts cookies to lines of the tab-separated fields domain, include
+/// subdomains, path, secure, expiry, name, and value. Session cookies have
+/// expiry 0.
+fn to_netscape(store: &CookieStore) -> String {
+    let mut content = String::from("# Netscape HTTP Cookie File\n");
+
+    for cookie in store.iter_unexpired() {
+        let (domain, include_subdomains) = match &cookie.domain {
+            CookieDomain::HostOnly(domain) => (domain.to_owned(), false),
+            CookieDomain::Suffix(domain) => (format!(".{}", domain), true),
+            CookieDomain::NotPresent | CookieDomain::Empty => continue,
+        };
+        let expiry = match &cookie.expires {
+            CookieExpiration::AtUtc(expiry) => expiry.unix_timestamp(),
+            CookieExpiration::SessionEnd => 0,
+        };
+        let prefix = if cookie.http_only().unwrap_or(false) {
+            HTTP_ONLY_PREFIX
+        } else {
+            ""
+        };
+
+        content.push_str(&format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            prefix,
+            domain,
+            netscape_bool(include_subdomains),
+            &*cookie.path,
+            netscape_bool(cookie.secure().unwrap_or(false)),
+            expiry,
+            cookie.name(),
+            cookie.value()
+        ));
+    }
+
+    content
+}
+
+fn from_netscape(content: &str) -> Result<CookieStore, anyhow::Error> {
+    let mut store = CookieStore::default();
+
+    for (i, line) in content.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(line) => (line, true),
+            None if line.trim().is_empty() || line.starts_with('#') => continue,
+            None => (line, false),
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        let (domain, include_subdomains, path, secure, expiry, name, value) = match fields[..] {
+            [domain, include_subdomains, path, secure, expiry, name, value] => (
+                domain,
+                include_subdomains,
+                path,
+                secure,
+                expiry,
+                name,
+                value,
+            ),
+            _ => return Err(anyhow!("Invalid cookie in line {}", i + 1)),
+        };
+        let expiry: i64 = expiry
+            .parse()
+            .context(format!("Invalid expiry in line {}", i + 1))?;
+
+        let mut cookie = format!("{}={}; Path={}", name, value, path);
+
+        if include_subdomains == "TRUE" {
+            cookie.push_str(&format!("; Domain={}", domain));
+        }
+
+        if secure == "TRUE" {
+            cookie.push_str("; Secure");
+        }
+
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+
+        let scheme = if secure == "TRUE" { "https" } else { "http" };
+        let url = Url::parse(&format!(
+            "{}://{}{}",
+            scheme,
+            domain.trim_start_matches('.'),
+            path
+        ))
+        .context(format!("Invalid domain or path in line {}", i + 1))?;
+
+        let mut cookie =
+            Cookie::parse(cookie, &url).context(format!("Invalid cookie in line {}", i + 1))?;
+
+        if expiry > 0 {
+            let expiry = OffsetDateTime::from_unix_timestamp(expiry)
+                .context(format!("Invalid expiry in line {}", i + 1))?;
+            cookie.expires = CookieExpiration::from(expiry);
+
+            if cookie.is_expired() {
+                continue;
+            }
+        }
+
+        store
+            .insert(cookie, &url)
+            .context(format!("Invalid cookie in line {}", i + 1))?;
+    }
+
+    Ok(store)
+}
+
+fn netscape_bool(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+    use tempfile::TempDir;
+
+    const NETSCAPE_COOKIES: &str = "# Netscape HTTP Cookie File
+127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc
+#HttpOnly_.example.com\tTRUE\t/api\tTRUE\t4102444800\ttoken\txyz
+example.com\tFALSE\t/\tFALSE\t1\texpired\told
+";
+
+    fn cookie_header(jar: &CookieJar, url: &str) -> Option<String> {
+        let mut request = Request::new(Method::GET, Url::parse(url).unwrap());
+        request
+            .headers_mut()
+            .insert(COOKIE, HeaderValue::from_static("manual=1; session=old"));
+        jar.add_cookie_header(&mut request).unwrap();
+
+        request
+            .headers()
+            .get(COOKIE)
+            .map(|header| header.to_str().unwrap().to_owned())
+    }
+
+    fn load_jar(format: CookieFormat, content: &str, dir: &TempDir, file_name: &str) -> CookieJar {
+        let path = dir.path().join(file_name);
+        fs::write(&path, content).unwrap();
+
+        CookieJar::load(&Cookies {
+            file: Some(path.to_str().unwrap().to_owned()),
+            format,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_netscape() {
+        let dir = tempfile::tempdir().unwrap();
+        let jar = load_jar(
+            CookieFormat::Netscape,
+            NETSCAPE_COOKIES,
+            &dir,
+            "cookies.txt",
+        );
+
+        assert_eq!(
+            cookie_header(&jar, "http://127.0.0.1:8080/"),
+            Some("manual=1; session=abc".to_owned())
+        );
+        assert_eq!(
+            cookie_header(&jar, "https://www.example.com/api/users"),
+            Some("manual=1; session=old; token=xyz".to_owned())
+        );
+        assert_eq!(
+            cookie_header(&jar, "http://www.example.com/api/users"),
+            Some("manual=1; session=old".to_owned())
+        );
+
+        jar.save().unwrap();
+
+        let saved = fs::read_to_string(jar.file.as_ref().unwrap()).unwrap();
+        let mut lines: Vec<&str> = saved.lines().collect();
+        lines.sort();
+
+        assert_eq!(
+            lines,
+            vec![
+                "# Netscape HTTP Cookie File",
+                "#HttpOnly_.example.com\tTRUE\t/api\tTRUE\t4102444800\ttoken\txyz",
+                "127.0.0.1\tFALSE\t/\tFALSE\t0\tsession\tabc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_netscape() {
+        assert_eq!(
+            from_netscape("example.com\tFALSE\t/\n")
+                .unwrap_err()
+                .to_string(),
+            "Invalid cookie in line 1"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let netscape_jar = load_jar(
+            CookieFormat::Netscape,
+            NETSCAPE_COOKIES,
+            &dir,
+            "cookies.txt",
+        );
+        let jar = load_jar(
+            CookieFormat::Json,
+            &to_json(&netscape_jar.store).unwrap(),
+            &dir,
+            "cookies.json",
+        );
+
+        assert_eq!(
+            cookie_header(&jar, "https://www.example.com/api/users"),
+            Some("manual=1; session=old; token=xyz".to_owned())
+        );
+
+        jar.save().unwrap();
+
+        let saved = fs::read_to_string(jar.file.as_ref().unwrap()).unwrap();
+        assert_eq!(saved.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_without_file() {
+        let jar = CookieJar::load(&Cookies {
+            file: None,
+            format: CookieFormat::default(),
+        })
+        .unwrap();
+
+        assert_eq!(
+            cookie_header(&jar, "http://127.0.0.1/"),
+            Some("manual=1; session=old".to_owned())
+        );
+        jar.save().unwrap();
+    }
+}
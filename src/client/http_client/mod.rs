@@ -1,32 +1,77 @@
+mod body;
 pub mod config;
+mod cookie_jar;
+mod redirect;
+mod render;
 pub mod request;
 pub mod response;
+mod stream;
+#[cfg(test)]
+pub(crate) mod test_server;
 
 use crate::client::{
+    auth::{self, Authenticator, Credentials},
     http_client::{
-        config::{Config, RequestMethod},
+        config::{Config, HttpVersion},
+        cookie_jar::CookieJar,
         request::Request,
         response::Response,
+        stream::{StreamKind, StreamParser},
     },
-    prettify_json,
+    outcome::{self, Outcome},
+    output,
+    timeout::{with_timeout, Timeout, TimeoutError},
 };
 use anyhow::Context;
-use log::info;
+use log::{error, info};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
-    Client, Url, Version,
+    redirect::Policy,
+    Client, ClientBuilder, Method, StatusCode, Url,
 };
 use serde_json::Value;
-use std::str::FromStr;
+use std::{fs, str::FromStr};
 
-pub async fn use_http_client(config_file: String) -> Result<(), anyhow::Error> {
+const LAST_EVENT_ID: &str = "last-event-id";
+
+pub async fn use_http_client(config_file: String) -> Result<Outcome, anyhow::Error> {
     info!("Using http client");
 
     let config: Config = serde_json::from_str(&config_file).context("Can't deserialize json")?;
     let url = format!("{}{}", config.api.url, config.api.endpoint);
     let url = Url::parse(&url)?;
 
-    let client = Client::new();
+    let mut cookie_jar = config.cookies.as_ref().map(CookieJar::load).transpose()?;
+
+    let result = with_timeout(
+        config.timeout.run(),
+        TimeoutError::Run,
+        send_request(config, url, cookie_jar.as_mut()),
+    )
+    .await;
+
+    // Cookies are saved even if the request failed, e.g. in a redirect loop
+    if let Some(cookie_jar) = &cookie_jar {
+        cookie_jar.save()?;
+    }
+
+    result?
+}
+
+async fn send_request(
+    config: Config,
+    url: Url,
+    mut cookie_jar: Option<&mut CookieJar>,
+) -> Result<Outcome, anyhow::Error> {
+    let version = config.api.version;
+    let mut client = client_builder(version);
+
+    if let Some(connect_timeout) = config.timeout.connect() {
+        client = client.connect_timeout(connect_timeout);
+    }
+
+    let client = client.build().context("Can't build http client")?;
+    let authenticator = config.auth.map(Authenticator::new);
 
     let mut header_map = HeaderMap::new();
 
@@ -38,55 +83,491 @@ pub async fn use_http_client(config_file: String) -> Result<(), anyhow::Error> {
         }
     }
 
-    let mut request = match config.request.method {
-        RequestMethod::Get => client.get(url),
-        RequestMethod::Post => client.post(url),
-        RequestMethod::Put => client.put(url),
-        RequestMethod::Delete => client.delete(url),
+    let mut request = client.request(Method::from(&config.request.method), url);
+
+    // The body is added first so that configured headers take precedence,
+    // e.g. a custom content type
+    let body = match config.request.body {
+        Some(body) => {
+            let (builder, body) = body::add_body(request, config.request.body_kind, body).await?;
+            request = builder;
+            Some(body)
+        }
+        None => None,
     };
 
-    request = request.version(Version::HTTP_11).headers(header_map);
+    request = request.headers(header_map);
+
+    if let Some(version) = version.to_version() {
+        request = request.version(version);
+    }
+
+    if let Some(request_timeout) = config.timeout.request() {
+        request = request.timeout(request_timeout);
+    }
 
     if let Some(value) = &config.request.query_string {
         request = request.query(value)
     };
 
-    if let Some(value) = &config.request.body {
-        request = request.json(value)
+    let mut request = request.build()?;
+
+    let credentials = match &authenticator {
+        Some(authenticator) => Some(authenticator.credentials().await?),
+        None => None,
     };
 
-    let request = request.build()?;
+    if let Some(credentials) = &credentials {
+        credentials.add_to_url(request.url_mut());
+        credentials.add_to_headers(request.headers_mut());
+    }
+
+    if let Some(cookie_jar) = &cookie_jar {
+        cookie_jar.add_cookie_header(&mut request)?;
+    }
+
+    let mut url = request.url().to_owned();
+    let mut headers = request.headers().to_owned();
+    auth::redact(&mut headers, &mut url, credentials.as_ref());
+
     let formatted_request = Request::new(
         request.version(),
         request.method().to_owned(),
-        request.url().to_owned(),
-        request.headers().to_owned(),
-        match config.request.body {
-            Some(body) => Some(prettify_json(body)?),
-            None => None,
-        },
+        url,
+        headers,
+        body,
     );
 
-    info!("Sending request\n{}", formatted_request);
+    if output::is_text() {
+        info!("Sending request\n{}", formatted_request);
+    } else {
+        output::emit(formatted_request.to_record());
+    }
 
-    let response = client.execute(request).await?;
+    let method = formatted_request.method;
+    let max_reconnects = config.response.max_reconnects.unwrap_or_default();
+    let mut parser: Option<StreamParser> = None;
+    let mut reconnects = 0;
 
-    let mut formatted_response = Response::new(
-        response.version(),
-        response.status(),
-        response.headers().to_owned(),
-        response.text().await?,
-    );
+    loop {
+        // Keep a copy of the request for reconnecting to event streams
+        let next_request = request.try_clone();
+
+        let response = redirect::execute(
+            &client,
+            request,
+            &config.redirect,
+            credentials.as_ref().and_then(Credentials::header_name),
+            cookie_jar.as_deref_mut(),
+            |err| map_version_error(map_timeout_error(err, &config.timeout), version),
+            |response| {
+                if output::is_text() {
+                    info!("Receiving redirect\n{}", response)
+                } else {
+                    output::emit(response.to_record("redirect", None))
+                }
+            },
+        )
+        .await;
+
+        let (result, is_event_stream) = match response {
+            Ok(response) => {
+                let stream_kind = match method {
+                    Method::HEAD => None,
+                    _ => stream::stream_kind(response.headers()),
+                };
+
+                let stream_kind = match stream_kind {
+                    Some(stream_kind) if response.status() == StatusCode::OK => stream_kind,
+                    _ => {
+                        return receive_response(
+                            response,
+                            &method,
+                            &config.response,
+                            &config.timeout,
+                        )
+                        .await
+                    }
+                };
+
+                let status = response.status();
+                let headers = response.headers().to_owned();
+
+                let formatted_response =
+                    Response::new(response.version(), status, headers.to_owned(), None);
+
+                if output::is_text() {
+                    info!("Receiving response\n{}", formatted_response);
+                } else {
+                    output::emit(formatted_response.to_record("response", None));
+                }
+
+                let parser = parser.get_or_insert_with(|| StreamParser::new(stream_kind));
+                let result = stream::receive(response, parser, &config.timeout, |item| {
+                    if output::is_text() {
+                        println!("{}", item)
+                    } else {
+                        output::emit(item.to_record())
+                    }
+                })
+                .await;
+
+                // The body of streaming responses is printed, but not kept
+                let result = result.map(|()| Outcome::Http {
+                    status,
+                    headers,
+                    body: Value::Null,
+                });
+
+                (result, stream_kind == StreamKind::EventStream)
+            }
+            // A failed reconnect to an event stream is retried like a
+            // dropped connection
+            Err(err) if reconnects > 0 && is_connection_error(&err) => (Err(err), true),
+            Err(err) => return Err(err),
+        };
+
+        let mut next_request = match next_request {
+            Some(next_request) if is_event_stream && reconnects < max_reconnects => next_request,
+            _ => return result,
+        };
+
+        match result {
+            Err(err) if err.downcast_ref::<TimeoutError>().is_some() => return Err(err),
+            Err(err) => error!("{:#}", err),
+            Ok(_) => info!("Event stream closed"),
+        }
+
+        if let Some(last_event_id) = parser.as_ref().and_then(StreamParser::last_event_id) {
+            next_request
+                .headers_mut()
+                .insert(LAST_EVENT_ID, HeaderValue::from_str(last_event_id)?);
+        }
+
+        // The url already contains query credentials, but tokens may have
+        // expired in the meantime
+        if let Some(authenticator) = &authenticator {
+            authenticator
+                .credentials()
+                .await?
+                .add_to_headers(next_request.headers_mut());
+        }
+
+        if let Some(cookie_jar) = &cookie_jar {
+            cookie_jar.add_cookie_header(&mut next_request)?;
+        }
+
+        request = next_request;
+
+        reconnects += 1;
+        let reconnection_time = parser
+            .as_ref()
+            .map_or(stream::DEFAULT_RETRY, StreamParser::reconnection_time);
+
+        info!(
+            "Reconnecting in {:?} ({}/{})",
+            reconnection_time, reconnects, max_reconnects
+        );
+
+        tokio::time::sleep(reconnection_time).await;
+    }
+}
+
+/// Receives the complete body of a non-streaming response.
+async fn receive_response(
+    response: reqwest::Response,
+    method: &Method,
+    response_config: &config::Response,
+    timeout: &Timeout,
+) -> Result<Outcome, anyhow::Error> {
+    let version = response.version();
+    let status = response.status();
+    let headers = response.headers().to_owned();
+
+    // Responses to HEAD requests don't have a body
+    let (body, record_body, outcome_body) = if method == Method::HEAD {
+        (None, None, Value::Null)
+    } else {
+        let body = response
+            .bytes()
+            .await
+            .map_err(|err| map_timeout_error(err, timeout))?;
 
-    if let Some(content_type) = formatted_response.headers.get(CONTENT_TYPE) {
-        if content_type.to_str()?.contains("application/json") {
-            if let Ok(response_body) = serde_json::from_str::<Value>(&formatted_response.body) {
-                formatted_response.body = prettify_json(response_body)?;
-            };
+        if let Some(path) = &response_config.save_body {
+            info!("Saving response body to '{}'", path);
+
+            fs::write(path, &body).context(format!("Can't write file '{}'", path))?;
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok());
+        (
+            Some(render::render(content_type, &body)),
+            Some(output::decode_body(&body)),
+            outcome::parse_payload(&body),
+        )
+    };
+
+    let formatted_response = Response::new(version, status, headers.to_owned(), body);
+
+    if output::is_text() {
+        info!("Receiving response\n{}", formatted_response);
+    } else {
+        output::emit(formatted_response.to_record("response", record_body));
+    }
+
+    Ok(Outcome::Http {
+        status,
+        headers,
+        body: outcome_body,
+    })
+}
+
+/// Creates a client builder which only uses the configured http version.
+/// Redirects are followed by `redirect::execute` instead of the client.
+fn client_builder(version: HttpVersion) -> ClientBuilder {
+    let client = Client::builder().redirect(Policy::none());
+
+    match version {
+        HttpVersion::Http11 => client.http1_only(),
+        HttpVersion::Http2PriorKnowledge => client.http2_prior_knowledge(),
+        HttpVersion::Http2 | HttpVersion::Auto => client,
+    }
+}
+
+/// Explains errors which may be caused by the server or the connection not
+/// supporting the configured http version.
+fn map_version_error(err: anyhow::Error, version: HttpVersion) -> anyhow::Error {
+    if err.downcast_ref::<TimeoutError>().is_some() {
+        return err;
+    }
+
+    match version {
+        HttpVersion::Http2 => err.context(
+            "Can't send request using http2. HTTP/2 is negotiated via TLS, use \
+            'http2-prior-knowledge' for servers supporting HTTP/2 without TLS",
+        ),
+        HttpVersion::Http2PriorKnowledge => err.context(
+            "Can't send request using http2-prior-knowledge. The server may not support HTTP/2",
+        ),
+        HttpVersion::Http11 | HttpVersion::Auto => err,
+    }
+}
+
+/// Checks if the error is caused by the connection, e.g. if it was refused or
+/// closed, and not by a timeout.
+fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<TimeoutError>().is_none()
+        && err
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|err| err.is_connect() || err.is_request() || err.is_body())
+}
+
+/// Converts errors caused by the configured connect or request timeout into
+/// the corresponding `TimeoutError`.
+fn map_timeout_error(err: reqwest::Error, timeout: &Timeout) -> anyhow::Error {
+    if err.is_timeout() {
+        match (err.is_connect(), timeout.connect(), timeout.request()) {
+            (true, Some(connect_timeout), _) => {
+                return anyhow::Error::new(err).context(TimeoutError::Connect(connect_timeout))
+            }
+            (_, _, Some(request_timeout)) => {
+                return anyhow::Error::new(err).context(TimeoutError::Request(request_timeout))
+            }
+            _ => (),
+        }
+    }
+
+    err.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use reqwest::{
+        header::{AUTHORIZATION, COOKIE, LOCATION, SET_COOKIE},
+        Version,
+    };
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// Sends a request with the http version and returns the version received
+    /// by the server.
+    async fn request_version(
+        version: &str,
+        http1_only: bool,
+    ) -> Result<Option<Version>, anyhow::Error> {
+        let received_version = Arc::new(Mutex::new(None));
+        let received = received_version.clone();
+        let addr = test_server::spawn(http1_only, move |request| {
+            *received.lock().unwrap() = Some(request.version());
+
+            async { hyper::Response::new(Body::from("ok")) }
+        });
+        let config = json!({
+            "api": { "url": format!("http://{}", addr), "endpoint": "/", "version": version },
+            "request": { "method": "GET" }
+        });
+
+        use_http_client(config.to_string()).await?;
+
+        let received_version = *received_version.lock().unwrap();
+        Ok(received_version)
+    }
+
+    #[tokio::test]
+    async fn test_http_version() {
+        assert_eq!(
+            request_version("http1.1", false).await.unwrap(),
+            Some(Version::HTTP_11)
+        );
+        assert_eq!(
+            request_version("auto", false).await.unwrap(),
+            Some(Version::HTTP_11)
+        );
+        assert_eq!(
+            request_version("http2-prior-knowledge", false)
+                .await
+                .unwrap(),
+            Some(Version::HTTP_2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http2_via_alpn() {
+        let addr = test_server::spawn_tls(|request| async move {
+            hyper::Response::new(Body::from(format!("{:?}", request.version())))
+        });
+        let ca =
+            reqwest::Certificate::from_pem(include_bytes!("../../../tests/tls/ca.pem")).unwrap();
+
+        for (version, expected_version) in [
+            (HttpVersion::Http2, Version::HTTP_2),
+            (HttpVersion::Auto, Version::HTTP_2),
+            (HttpVersion::Http11, Version::HTTP_11),
+        ] {
+            let client = client_builder(version)
+                .add_root_certificate(ca.clone())
+                .build()
+                .unwrap();
+            let mut request = client.get(format!("https://localhost:{}/", addr.port()));
+
+            if let Some(version) = version.to_version() {
+                request = request.version(version);
+            }
+
+            let response = request.send().await.unwrap();
+
+            assert_eq!(response.version(), expected_version);
+            assert_eq!(
+                response.text().await.unwrap(),
+                format!("{:?}", expected_version)
+            );
         }
     }
 
-    info!("Receiving response\n{}", formatted_response);
+    #[tokio::test]
+    async fn test_cookies() {
+        let received_cookies = Arc::new(Mutex::new(vec![]));
+        let received = received_cookies.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let path = request.uri().path().to_owned();
+            let cookie = request
+                .headers()
+                .get(COOKIE)
+                .map(|cookie| cookie.to_str().unwrap().to_owned());
+            received.lock().unwrap().push((path.clone(), cookie));
+
+            async move {
+                match path.as_str() {
+                    "/login" => hyper::Response::builder()
+                        .status(StatusCode::FOUND)
+                        .header(LOCATION, "/home")
+                        .header(SET_COOKIE, "session=abc; Path=/; HttpOnly")
+                        .body(Body::empty())
+                        .unwrap(),
+                    _ => hyper::Response::new(Body::from("ok")),
+                }
+            }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let cookie_file = dir.path().join("cookies.txt");
+        let config = |endpoint: &str| {
+            json!({
+                "api": { "url": format!("http://{}", addr), "endpoint": endpoint },
+                "request": { "method": "GET" },
+                "cookies": { "file": cookie_file }
+            })
+            .to_string()
+        };
+
+        use_http_client(config("/login")).await.unwrap();
+        use_http_client(config("/home")).await.unwrap();
+
+        let session_cookie = Some("session=abc".to_owned());
+        assert_eq!(
+            *received_cookies.lock().unwrap(),
+            vec![
+                ("/login".to_owned(), None),
+                ("/home".to_owned(), session_cookie.clone()),
+                ("/home".to_owned(), session_cookie)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth() {
+        let received_requests = Arc::new(Mutex::new(vec![]));
+        let received = received_requests.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let authorization = request
+                .headers()
+                .get(AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_owned());
+            received
+                .lock()
+                .unwrap()
+                .push((request.uri().to_string(), authorization));
 
-    Ok(())
+            async { hyper::Response::new(Body::from("ok")) }
+        });
+        let config = |auth: serde_json::Value| {
+            json!({
+                "api": { "url": format!("http://{}", addr), "endpoint": "/" },
+                "request": { "method": "GET", "query_string": { "page": "1" } },
+                "auth": auth
+            })
+            .to_string()
+        };
+
+        use_http_client(config(json!({ "type": "bearer", "token": "my_token" })))
+            .await
+            .unwrap();
+        use_http_client(config(
+            json!({ "type": "api_key", "name": "key", "value": "my_key", "in": "query" }),
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *received_requests.lock().unwrap(),
+            vec![
+                ("/?page=1".to_owned(), Some("Bearer my_token".to_owned())),
+                ("/?page=1&key=my_key".to_owned(), None)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_http_version() {
+        let err = request_version("http2", false).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("HTTP/2 is negotiated via TLS"));
+
+        let err = request_version("http2-prior-knowledge", true)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("The server may not support HTTP/2"));
+    }
 }
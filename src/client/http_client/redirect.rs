@@ -0,0 +1,450 @@
+//! Follows redirects manually instead of letting reqwest follow them, so that
+//! every hop can be printed.
+
+use crate::client::http_client::{config::Redirect, cookie_jar::CookieJar, response::Response};
+use anyhow::anyhow;
+use log::{info, warn};
+use reqwest::{
+    header::{
+        HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+        COOKIE, LOCATION, PROXY_AUTHORIZATION, TRANSFER_ENCODING, WWW_AUTHENTICATE,
+    },
+    Client, Method, Request, StatusCode, Url,
+};
+
+/// Headers which aren't sent to another origin
+const SENSITIVE_HEADERS: [HeaderName; 4] =
+    [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE];
+
+/// Headers which describe the body and are removed with it
+const BODY_HEADERS: [HeaderName; 4] = [
+    CONTENT_ENCODING,
+    CONTENT_LENGTH,
+    CONTENT_TYPE,
+    TRANSFER_ENCODING,
+];
+
+/// Executes the request and follows redirects according to the redirect
+/// config. Each redirect response is passed to `on_redirect` before it is
+/// followed, and the final response is returned. The client must not follow
+/// redirects itself. Cookies of every response are stored in the cookie jar,
+/// and sent with the redirected requests. With `max` of 0, redirects aren't
+/// followed. Like the sensitive headers, the header with the credentials,
+/// e.g. of an api key, isn't sent to another origin.
+pub async fn execute<E, R>(
+    client: &Client,
+    mut request: Request,
+    redirect: &Redirect,
+    credential_header: Option<&HeaderName>,
+    mut cookie_jar: Option<&mut CookieJar>,
+    map_err: E,
+    mut on_redirect: R,
+) -> Result<reqwest::Response, anyhow::Error>
+where
+    E: Fn(reqwest::Error) -> anyhow::Error,
+    R: FnMut(Response),
+{
+    let mut visited = vec![visit(&request)];
+
+    loop {
+        let method = request.method().to_owned();
+        // Streamed bodies can't be cloned, so a copy without body is kept as
+        // well for redirects which change the method
+        let with_body = request.try_clone();
+        let without_body = without_body(&request);
+
+        let response = client.execute(request).await.map_err(&map_err)?;
+
+        if let Some(cookie_jar) = cookie_jar.as_deref_mut() {
+            cookie_jar.store_response_cookies(&response);
+        }
+
+        let status = response.status();
+        let location = match response.headers().get(LOCATION) {
+            Some(location) if redirect.follow && redirect.max > 0 && is_redirect(status) => {
+                location
+            }
+            _ => return Ok(response),
+        };
+        let url = location
+            .to_str()
+            .ok()
+            .and_then(|location| response.url().join(location).ok())
+            .ok_or_else(|| anyhow!("Can't follow redirect to invalid location {:?}", location))?;
+
+        on_redirect(Response::new(
+            response.version(),
+            status,
+            response.headers().to_owned(),
+            None,
+        ));
+
+        if visited.len() > redirect.max {
+            return Err(anyhow!(
+                "Too many redirects, stopped after {}",
+                redirect.max
+            ));
+        }
+
+        let next_method = redirect_method(status, &method);
+        let mut next_request = if next_method == method {
+            match with_body {
+                Some(next_request) => next_request,
+                None => {
+                    warn!(
+                        "Can't follow redirect to '{}', the request body can't be sent again",
+                        url
+                    );
+                    return Ok(response);
+                }
+            }
+        } else {
+            without_body
+        };
+
+        *next_request.method_mut() = next_method;
+
+        if !is_same_origin(next_request.url(), &url) {
+            for header in SENSITIVE_HEADERS.iter().chain(credential_header) {
+                next_request.headers_mut().remove(header);
+            }
+        }
+
+        *next_request.url_mut() = url.to_owned();
+
+        if let Some(cookie_jar) = cookie_jar.as_deref() {
+            cookie_jar.add_cookie_header(&mut next_request)?;
+        }
+
+        // A url may be requested again, e.g. after a login sets a cookie, but
+        // not with the same method and cookies
+        let visit = visit(&next_request);
+
+        if visited.contains(&visit) {
+            return Err(anyhow!(
+                "Redirect loop detected, '{}' was already requested",
+                url
+            ));
+        }
+
+        info!("Following redirect to '{}'", url);
+
+        visited.push(visit);
+        request = next_request;
+    }
+}
+
+/// The method, url, and cookies of a request, which identify a redirect loop.
+fn visit(request: &Request) -> (Method, Url, Option<HeaderValue>) {
+    (
+        request.method().to_owned(),
+        request.url().to_owned(),
+        request.headers().get(COOKIE).cloned(),
+    )
+}
+
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+/// The method of the redirected request, which is changed to `GET` like
+/// browsers do.
+fn redirect_method(status: StatusCode, method: &Method) -> Method {
+    match status {
+        StatusCode::SEE_OTHER if method != Method::HEAD => Method::GET,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if method == Method::POST => Method::GET,
+        _ => method.to_owned(),
+    }
+}
+
+fn without_body(request: &Request) -> Request {
+    let mut copy = Request::new(request.method().to_owned(), request.url().to_owned());
+    *copy.headers_mut() = request.headers().to_owned();
+    *copy.version_mut() = request.version();
+    *copy.timeout_mut() = request.timeout().copied();
+
+    for header in &BODY_HEADERS {
+        copy.headers_mut().remove(header);
+    }
+
+    copy
+}
+
+fn is_same_origin(url: &Url, other: &Url) -> bool {
+    url.scheme() == other.scheme()
+        && url.host_str() == other.host_str()
+        && url.port_or_known_default() == other.port_or_known_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::test_server;
+    use hyper::Body;
+    use reqwest::{header::SET_COOKIE, redirect::Policy};
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    fn redirect_response(status: StatusCode, location: &str) -> hyper::Response<Body> {
+        hyper::Response::builder()
+            .status(status)
+            .header(LOCATION, location)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Starts a server which redirects `/see-other` to `/temporary` to
+    /// `/final`, `/loop` to itself, and `/login` to `/session` back to
+    /// `/login`, which sets a cookie and is only redirected without cookie.
+    /// The method and path of every request are recorded.
+    fn spawn_server() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let path = request.uri().path().to_owned();
+            let request_cookie = request.headers().get(COOKIE).cloned();
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.method(), path));
+
+            async move {
+                match path.as_str() {
+                    "/see-other" => redirect_response(StatusCode::SEE_OTHER, "/temporary"),
+                    "/temporary" => redirect_response(StatusCode::TEMPORARY_REDIRECT, "final"),
+                    "/loop" => redirect_response(StatusCode::FOUND, "/loop"),
+                    "/login" if request_cookie.is_none() => {
+                        let mut response = redirect_response(StatusCode::FOUND, "/session");
+                        response
+                            .headers_mut()
+                            .insert(SET_COOKIE, "session=abc".parse().unwrap());
+                        response
+                    }
+                    "/session" => redirect_response(StatusCode::FOUND, "/login"),
+                    _ => hyper::Response::new(Body::from("ok")),
+                }
+            }
+        });
+
+        (addr, requests)
+    }
+
+    async fn execute_request(
+        request: Request,
+        redirect: &Redirect,
+    ) -> (Result<reqwest::Response, anyhow::Error>, Vec<Response>) {
+        execute_with_cookies(request, redirect, None).await
+    }
+
+    async fn execute_with_cookies(
+        request: Request,
+        redirect: &Redirect,
+        cookie_jar: Option<&mut CookieJar>,
+    ) -> (Result<reqwest::Response, anyhow::Error>, Vec<Response>) {
+        let client = Client::builder().redirect(Policy::none()).build().unwrap();
+        let mut redirects = vec![];
+        let result = execute(
+            &client,
+            request,
+            redirect,
+            None,
+            cookie_jar,
+            anyhow::Error::new,
+            |response| redirects.push(response),
+        )
+        .await;
+
+        (result, redirects)
+    }
+
+    #[tokio::test]
+    async fn test_follow() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/see-other", addr)).unwrap();
+        let (result, redirects) =
+            execute_request(Request::new(Method::POST, url), &Redirect::default()).await;
+
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            redirects
+                .iter()
+                .map(|response| (response.status, response.headers[LOCATION].to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                (StatusCode::SEE_OTHER, "/temporary".parse().unwrap()),
+                (StatusCode::TEMPORARY_REDIRECT, "final".parse().unwrap())
+            ]
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /see-other", "GET /temporary", "GET /final"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/see-other", addr)).unwrap();
+        let redirect = Redirect {
+            follow: false,
+            ..Redirect::default()
+        };
+        let (result, redirects) = execute_request(Request::new(Method::GET, url), &redirect).await;
+
+        assert_eq!(result.unwrap().status(), StatusCode::SEE_OTHER);
+        assert!(redirects.is_empty());
+        assert_eq!(*requests.lock().unwrap(), vec!["GET /see-other"]);
+    }
+
+    #[tokio::test]
+    async fn test_max_zero() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/see-other", addr)).unwrap();
+        let redirect = Redirect {
+            max: 0,
+            ..Redirect::default()
+        };
+        let (result, redirects) = execute_request(Request::new(Method::GET, url), &redirect).await;
+
+        assert_eq!(result.unwrap().status(), StatusCode::SEE_OTHER);
+        assert!(redirects.is_empty());
+        assert_eq!(*requests.lock().unwrap(), vec!["GET /see-other"]);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_redirects() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/see-other", addr)).unwrap();
+        let redirect = Redirect {
+            max: 1,
+            ..Redirect::default()
+        };
+        let (result, redirects) = execute_request(Request::new(Method::GET, url), &redirect).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Too many redirects, stopped after 1"
+        );
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /see-other", "GET /temporary"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_loop() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/loop", addr)).unwrap();
+        let (result, redirects) = execute_request(
+            Request::new(Method::GET, url.to_owned()),
+            &Redirect::default(),
+        )
+        .await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("Redirect loop detected, '{}' was already requested", url)
+        );
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(*requests.lock().unwrap(), vec!["GET /loop"]);
+    }
+
+    #[tokio::test]
+    async fn test_revisit_with_cookie() {
+        let (addr, requests) = spawn_server();
+        let url = Url::parse(&format!("http://{}/login", addr)).unwrap();
+        let mut cookie_jar = CookieJar::load(&serde_json::from_str("{}").unwrap()).unwrap();
+        let (result, redirects) = execute_with_cookies(
+            Request::new(Method::GET, url),
+            &Redirect::default(),
+            Some(&mut cookie_jar),
+        )
+        .await;
+
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+        assert_eq!(redirects.len(), 2);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /login", "GET /session", "GET /login"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sensitive_headers() {
+        let api_key = HeaderName::from_static("x-api-key");
+        let received_headers = Arc::new(Mutex::new(vec![]));
+        let received = received_headers.clone();
+        let other_addr = test_server::spawn(false, move |request| {
+            let headers = request.headers();
+            received.lock().unwrap().extend([
+                headers.get(AUTHORIZATION).cloned(),
+                headers.get("x-api-key").cloned(),
+                headers.get("x-request-id").cloned(),
+            ]);
+
+            async { hyper::Response::new(Body::empty()) }
+        });
+        let addr = test_server::spawn(false, move |_| async move {
+            redirect_response(StatusCode::FOUND, &format!("http://{}/", other_addr))
+        });
+        let url = Url::parse(&format!("http://{}/", addr)).unwrap();
+        let mut request = Request::new(Method::GET, url);
+        let headers = request.headers_mut();
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        headers.insert(&api_key, "my_key".parse().unwrap());
+        headers.insert("x-request-id", "1".parse().unwrap());
+        let client = Client::builder().redirect(Policy::none()).build().unwrap();
+
+        let response = execute(
+            &client,
+            request,
+            &Redirect::default(),
+            Some(&api_key),
+            None,
+            anyhow::Error::new,
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            *received_headers.lock().unwrap(),
+            vec![None, None, Some(HeaderValue::from_static("1"))]
+        );
+    }
+
+    #[test]
+    fn test_redirect_method() {
+        assert_eq!(
+            redirect_method(StatusCode::SEE_OTHER, &Method::PUT),
+            Method::GET
+        );
+        assert_eq!(
+            redirect_method(StatusCode::SEE_OTHER, &Method::HEAD),
+            Method::HEAD
+        );
+        assert_eq!(
+            redirect_method(StatusCode::FOUND, &Method::POST),
+            Method::GET
+        );
+        assert_eq!(
+            redirect_method(StatusCode::MOVED_PERMANENTLY, &Method::PUT),
+            Method::PUT
+        );
+        assert_eq!(
+            redirect_method(StatusCode::PERMANENT_REDIRECT, &Method::POST),
+            Method::POST
+        );
+    }
+}
@@ -0,0 +1,539 @@
+//! Renders http response bodies for display according to their media type.
+
+use crate::client::prettify_json;
+use encoding_rs::{Encoding, UTF_8};
+use mime::Mime;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// The maximum number of bytes shown in a hex dump.
+const MAX_HEX_DUMP_BYTES: usize = 1024;
+const BYTES_PER_LINE: usize = 16;
+const INDENT: &str = "    ";
+
+/// HTML elements without closing tag.
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// HTML elements whose content is not parsed as markup.
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+#[derive(Debug, PartialEq)]
+enum MediaKind {
+    Json,
+    Ndjson,
+    Xml,
+    Html,
+    Text,
+    Image,
+    Pdf,
+    Binary,
+}
+
+/// Renders the body according to the media type of the `Content-Type`
+/// header. Without content type, the body is rendered as json or text if
+/// possible, and as hex dump otherwise.
+pub fn render(content_type: Option<&str>, body: &[u8]) -> String {
+    let mime = content_type.and_then(|content_type| content_type.parse::<Mime>().ok());
+
+    match media_kind(mime.as_ref(), body) {
+        MediaKind::Json => {
+            let text = decode(mime.as_ref(), body);
+            render_json(&text).unwrap_or(text)
+        }
+        MediaKind::Ndjson => render_ndjson(&decode(mime.as_ref(), body)),
+        MediaKind::Xml => {
+            let text = decode(mime.as_ref(), body);
+            prettify_markup(&text, false).unwrap_or(text)
+        }
+        MediaKind::Html => {
+            let text = decode(mime.as_ref(), body);
+            prettify_markup(&text, true).unwrap_or(text)
+        }
+        MediaKind::Text => decode(mime.as_ref(), body),
+        MediaKind::Image => summarize_image(mime.as_ref(), body),
+        MediaKind::Pdf => summarize_pdf(body),
+        MediaKind::Binary => hex_dump(body),
+    }
+}
+
+fn media_kind(mime: Option<&Mime>, body: &[u8]) -> MediaKind {
+    let mime = match mime {
+        Some(mime) => mime,
+        None => {
+            return match std::str::from_utf8(body) {
+                Ok(text) if serde_json::from_str::<Value>(text).is_ok() => MediaKind::Json,
+                Ok(text) if is_text(text) => MediaKind::Text,
+                _ => MediaKind::Binary,
+            }
+        }
+    };
+
+    let subtype = mime.subtype().as_str();
+    let suffix = mime.suffix().map(|suffix| suffix.as_str());
+
+    match (mime.type_().as_str(), subtype, suffix) {
+        (_, "json", _) | (_, _, Some("json")) => MediaKind::Json,
+        ("application", "x-ndjson" | "ndjson" | "jsonl" | "x-jsonlines" | "jsonlines", _) => {
+            MediaKind::Ndjson
+        }
+        (_, "xml", _) | (_, _, Some("xml")) => MediaKind::Xml,
+        ("text", "html", _) => MediaKind::Html,
+        ("text", _, _) => MediaKind::Text,
+        ("application", "javascript" | "x-www-form-urlencoded", _) => MediaKind::Text,
+        ("image", _, _) => MediaKind::Image,
+        ("application", "pdf", _) => MediaKind::Pdf,
+        _ => match std::str::from_utf8(body) {
+            Ok(text) if !text.is_empty() && is_text(text) && subtype != "octet-stream" => {
+                MediaKind::Text
+            }
+            _ => MediaKind::Binary,
+        },
+    }
+}
+
+/// Checks if the text contains no control characters except whitespace.
+fn is_text(text: &str) -> bool {
+    text.chars()
+        .all(|char| !char.is_control() || char.is_whitespace())
+}
+
+/// Decodes the body by the charset of the media type, or as UTF-8 if missing.
+fn decode(mime: Option<&Mime>, body: &[u8]) -> String {
+    let encoding = mime
+        .and_then(|mime| mime.get_param(mime::CHARSET))
+        .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+fn render_json(text: &str) -> Option<String> {
+    let value = serde_json::from_str::<Value>(text).ok()?;
+    prettify_json(value).ok()
+}
+
+fn render_ndjson(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| render_json(line).unwrap_or_else(|| line.to_owned()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Open(&'a str, &'a str),
+    Close(&'a str),
+    /// Self-closing tags, void elements, comments, declarations, and
+    /// processing instructions
+    Empty(&'a str),
+    Text(&'a str),
+}
+
+/// Indents XML, or HTML if `html` is set. Returns `None` if the markup is
+/// not well-formed.
+fn prettify_markup(text: &str, html: bool) -> Option<String> {
+    let tokens = tokenize(text, html)?;
+    let mut output = String::new();
+    let mut names = vec![];
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::Open(tag, name) => {
+                // Keep elements with only text content on a single line
+                if let (Some(Token::Text(text)), Some(Token::Close(close))) =
+                    (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    if close.eq_ignore_ascii_case(name) {
+                        writeln!(
+                            output,
+                            "{}{}{}</{}>",
+                            INDENT.repeat(names.len()),
+                            tag,
+                            text,
+                            close
+                        )
+                        .ok()?;
+                        i += 3;
+                        continue;
+                    }
+                }
+
+                writeln!(output, "{}{}", INDENT.repeat(names.len()), tag).ok()?;
+                names.push(name);
+            }
+            Token::Close(name) => {
+                if !names.pop()?.eq_ignore_ascii_case(name) {
+                    return None;
+                }
+
+                writeln!(output, "{}</{}>", INDENT.repeat(names.len()), name).ok()?;
+            }
+            Token::Empty(tag) => writeln!(output, "{}{}", INDENT.repeat(names.len()), tag).ok()?,
+            Token::Text(text) => writeln!(output, "{}{}", INDENT.repeat(names.len()), text).ok()?,
+        }
+
+        i += 1;
+    }
+
+    if !names.is_empty() {
+        return None;
+    }
+
+    Some(output.trim_end().to_owned())
+}
+
+fn tokenize(text: &str, html: bool) -> Option<Vec<Token<'_>>> {
+    let mut tokens = vec![];
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+
+            if !text.is_empty() {
+                tokens.push(Token::Text(text));
+            }
+
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>")? + 3
+        } else {
+            find_tag_end(rest)? + 1
+        };
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if tag.starts_with("<!") || tag.starts_with("<?") || tag.ends_with("/>") {
+            tokens.push(Token::Empty(tag));
+        } else if let Some(name) = tag.strip_prefix("</") {
+            tokens.push(Token::Close(name.trim_end_matches('>').trim()));
+        } else {
+            let name = tag[1..]
+                .split(|char: char| char.is_whitespace() || char == '>')
+                .next()?;
+
+            if html && VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                tokens.push(Token::Empty(tag));
+            } else if html && RAW_TEXT_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                let end = rest
+                    .to_ascii_lowercase()
+                    .find(&format!("</{}", name.to_ascii_lowercase()))?;
+                tokens.push(Token::Open(tag, name));
+
+                if !rest[..end].trim().is_empty() {
+                    tokens.push(Token::Text(rest[..end].trim()));
+                }
+
+                rest = &rest[end..];
+            } else {
+                tokens.push(Token::Open(tag, name));
+            }
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Finds the closing `>` of a tag, skipping quoted attribute values.
+fn find_tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+
+    for (i, char) in text.char_indices() {
+        match (quote, char) {
+            (None, '"' | '\'') => quote = Some(char),
+            (Some(q), _) if q == char => quote = None,
+            (None, '>') => return Some(i),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn summarize_image(mime: Option<&Mime>, body: &[u8]) -> String {
+    let media_type = mime.map(|mime| mime.essence_str()).unwrap_or("image");
+
+    match image_dimensions(body) {
+        Some((width, height)) => format!(
+            "Image ({}, {} bytes, {}x{} pixels)",
+            media_type,
+            body.len(),
+            width,
+            height
+        ),
+        None => format!("Image ({}, {} bytes)", media_type, body.len()),
+    }
+}
+
+/// Reads the dimensions from the header of PNG, GIF, and JPEG images.
+fn image_dimensions(body: &[u8]) -> Option<(u32, u32)> {
+    if body.starts_with(b"\x89PNG\r\n\x1a\n") && body.len() >= 24 {
+        let width = u32::from_be_bytes(body[16..20].try_into().ok()?);
+        let height = u32::from_be_bytes(body[20..24].try_into().ok()?);
+        return Some((width, height));
+    }
+
+    if (body.starts_with(b"GIF87a") || body.starts_with(b"GIF89a")) && body.len() >= 10 {
+        let width = u16::from_le_bytes([body[6], body[7]]);
+        let height = u16::from_le_bytes([body[8], body[9]]);
+        return Some((width.into(), height.into()));
+    }
+
+    if body.starts_with(&[0xff, 0xd8]) {
+        let mut i = 2;
+
+        // Find the start of frame segment (SOF0 to SOF15, except DHT, JPG,
+        // and DAC)
+        while i + 9 < body.len() && body[i] == 0xff {
+            let marker = body[i + 1];
+            let length = usize::from(u16::from_be_bytes([body[i + 2], body[i + 3]]));
+
+            if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                let height = u16::from_be_bytes([body[i + 5], body[i + 6]]);
+                let width = u16::from_be_bytes([body[i + 7], body[i + 8]]);
+                return Some((width.into(), height.into()));
+            }
+
+            i += 2 + length;
+        }
+    }
+
+    None
+}
+
+fn summarize_pdf(body: &[u8]) -> String {
+    let version = body
+        .strip_prefix(b"%PDF-")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|version| std::str::from_utf8(version).ok());
+
+    match version {
+        Some(version) => format!(
+            "PDF document (version {}, {} bytes, {} pages)",
+            version,
+            body.len(),
+            count_pdf_pages(body)
+        ),
+        None => format!("PDF document ({} bytes)", body.len()),
+    }
+}
+
+/// Counts the page objects, i.e. `/Type /Page` but not `/Type /Pages`.
+fn count_pdf_pages(body: &[u8]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+
+    while let Some(position) = find(&body[i..], b"/Type") {
+        i += position + 5;
+
+        let rest = &body[i..];
+        let start = rest
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+
+        if rest[start..].starts_with(b"/Page") && rest.get(start + 5) != Some(&b's') {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Formats the body like `hexdump -C`, truncated after
+/// `MAX_HEX_DUMP_BYTES`.
+fn hex_dump(body: &[u8]) -> String {
+    let mut output = format!("Binary ({} bytes)", body.len());
+
+    for (i, chunk) in body
+        .chunks(BYTES_PER_LINE)
+        .take(MAX_HEX_DUMP_BYTES / BYTES_PER_LINE)
+        .enumerate()
+    {
+        let hex = (0..BYTES_PER_LINE)
+            .map(|j| match chunk.get(j) {
+                Some(byte) => format!("{:02x} ", byte),
+                None => "   ".to_owned(),
+            })
+            .enumerate()
+            .fold(String::new(), |mut hex, (j, byte)| {
+                if j == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                hex.push_str(&byte);
+                hex
+            });
+        let ascii = chunk
+            .iter()
+            .map(|byte| match byte {
+                0x20..=0x7e => *byte as char,
+                _ => '.',
+            })
+            .collect::<String>();
+
+        output.push_str(&format!(
+            "\n{:08x}  {} |{}|",
+            i * BYTES_PER_LINE,
+            hex,
+            ascii
+        ));
+    }
+
+    if body.len() > MAX_HEX_DUMP_BYTES {
+        output.push_str(&format!(
+            "\n... {} more bytes",
+            body.len() - MAX_HEX_DUMP_BYTES
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_kind() {
+        let media_kinds = [
+            ("application/json", MediaKind::Json),
+            ("application/problem+json", MediaKind::Json),
+            ("application/vnd.api+json; charset=utf-8", MediaKind::Json),
+            ("application/x-ndjson", MediaKind::Ndjson),
+            ("application/xml", MediaKind::Xml),
+            ("text/xml", MediaKind::Xml),
+            ("application/atom+xml", MediaKind::Xml),
+            ("text/html; charset=utf-8", MediaKind::Html),
+            ("text/plain", MediaKind::Text),
+            ("image/png", MediaKind::Image),
+            ("application/pdf", MediaKind::Pdf),
+            ("application/octet-stream", MediaKind::Binary),
+        ];
+
+        for (content_type, media_kind) in media_kinds {
+            let mime = content_type.parse::<Mime>().unwrap();
+            assert_eq!(super::media_kind(Some(&mime), b"{}"), media_kind);
+        }
+
+        assert_eq!(super::media_kind(None, b"{}"), MediaKind::Json);
+        assert_eq!(super::media_kind(None, b"hello"), MediaKind::Text);
+        assert_eq!(super::media_kind(None, &[0, 159]), MediaKind::Binary);
+    }
+
+    #[test]
+    fn test_render_json() {
+        let rendered = render(Some("application/problem+json"), br#"{"status":404}"#);
+        assert_eq!(rendered, "{\n    \"status\": 404\n}");
+
+        let rendered = render(Some("application/json"), b"not json");
+        assert_eq!(rendered, "not json");
+    }
+
+    #[test]
+    fn test_render_ndjson() {
+        let rendered = render(Some("application/x-ndjson"), b"{\"a\":1}\n\n{\"b\":2}\n");
+        assert_eq!(rendered, "{\n    \"a\": 1\n}\n{\n    \"b\": 2\n}");
+    }
+
+    #[test]
+    fn test_render_xml() {
+        let body =
+            br#"<?xml version="1.0"?><root a="x>y"><item>one</item><empty/><!-- note --></root>"#;
+        let rendered = render(Some("application/xml"), body);
+        let expected = r#"<?xml version="1.0"?>
+<root a="x>y">
+    <item>one</item>
+    <empty/>
+    <!-- note -->
+</root>"#;
+        assert_eq!(rendered, expected);
+
+        for body in ["<root><item></root>", "<root></item>", "<root><item"] {
+            assert_eq!(render(Some("application/xml"), body.as_bytes()), body);
+        }
+    }
+
+    #[test]
+    fn test_render_html() {
+        let body = b"<!DOCTYPE html><html><head><meta charset=\"utf-8\"><script>if (a < b) {}</script></head><body><p>Hi<br>there</p></body></html>";
+        let rendered = render(Some("text/html"), body);
+        let expected = r#"<!DOCTYPE html>
+<html>
+    <head>
+        <meta charset="utf-8">
+        <script>if (a < b) {}</script>
+    </head>
+    <body>
+        <p>
+            Hi
+            <br>
+            there
+        </p>
+    </body>
+</html>"#;
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_text_with_charset() {
+        let rendered = render(
+            Some("text/plain; charset=iso-8859-1"),
+            &[0x63, 0x61, 0x66, 0xe9],
+        );
+        assert_eq!(rendered, "café");
+    }
+
+    #[test]
+    fn test_render_image() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+
+        assert_eq!(
+            render(Some("image/png"), &png),
+            "Image (image/png, 24 bytes, 640x480 pixels)"
+        );
+        assert_eq!(
+            render(Some("image/gif"), b"GIF89a\x02\0\x03\0"),
+            "Image (image/gif, 10 bytes, 2x3 pixels)"
+        );
+    }
+
+    #[test]
+    fn test_render_pdf() {
+        let body = b"%PDF-1.7\n1 0 obj << /Type /Pages /Count 2 >>\n2 0 obj << /Type /Page >>\n3 0 obj << /Type/Page >>";
+        assert_eq!(
+            render(Some("application/pdf"), body),
+            format!("PDF document (version 1.7, {} bytes, 2 pages)", body.len())
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let rendered = render(Some("application/octet-stream"), b"0123456789abcdefXYZ\n");
+        let expected = "Binary (20 bytes)
+00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|
+00000010  58 59 5a 0a                                       |XYZ.|";
+        assert_eq!(rendered, expected);
+
+        let rendered = hex_dump(&[0; MAX_HEX_DUMP_BYTES + 1]);
+        assert!(rendered.ends_with("\n... 1 more bytes"));
+    }
+}
@@ -1,4 +1,9 @@
-use reqwest::{header::HeaderMap, Method, Url, Version};
+use crate::client::output::{Direction, Record};
+use reqwest::{
+    header::{HeaderMap, COOKIE},
+    Method, Url, Version,
+};
+use serde_json::{json, Map, Value};
 use std::fmt;
 
 #[derive(Debug)]
@@ -7,7 +12,27 @@ pub struct Request {
     pub method: Method,
     pub url: Url,
     pub headers: HeaderMap,
-    pub body: Option<String>,
+    pub body: Option<Body>,
+}
+
+/// The request body as displayed, depending on the body kind.
+#[derive(Debug)]
+pub enum Body {
+    Json(String),
+    Text(String),
+    Form(Vec<(String, String)>),
+    Multipart {
+        fields: Vec<(String, String)>,
+        files: Vec<(String, FileInfo)>,
+    },
+    File(FileInfo),
+}
+
+#[derive(Debug)]
+pub struct FileInfo {
+    pub path: String,
+    pub content_type: String,
+    pub size: u64,
 }
 
 impl Request {
@@ -16,7 +41,7 @@ impl Request {
         method: Method,
         url: Url,
         headers: HeaderMap,
-        body: Option<String>,
+        body: Option<Body>,
     ) -> Self {
         Self {
             version,
@@ -26,6 +51,57 @@ impl Request {
             body,
         }
     }
+
+    pub fn to_record(&self) -> Record {
+        let record = Record::new("http", Direction::Sent, "request")
+            .detail("method", self.method.as_str())
+            .detail("url", self.url.as_str())
+            .detail("version", format!("{:?}", self.version))
+            .headers(&self.headers);
+
+        match &self.body {
+            Some(body) => record.body(body.to_value()),
+            None => record,
+        }
+    }
+}
+
+impl Body {
+    fn to_value(&self) -> Value {
+        let fields = |fields: &[(String, String)]| {
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_owned(), Value::String(value.to_owned())))
+                .collect::<Map<_, _>>()
+        };
+
+        match self {
+            Body::Json(json) => serde_json::from_str(json).unwrap_or_else(|_| json!(json)),
+            Body::Text(text) => json!(text),
+            Body::Form(form) => Value::Object(fields(form)),
+            Body::Multipart {
+                fields: multipart_fields,
+                files,
+            } => json!({
+                "fields": fields(multipart_fields),
+                "files": files
+                    .iter()
+                    .map(|(key, file)| (key.to_owned(), file.to_value()))
+                    .collect::<Map<_, _>>(),
+            }),
+            Body::File(file) => file.to_value(),
+        }
+    }
+}
+
+impl FileInfo {
+    fn to_value(&self) -> Value {
+        json!({
+            "path": self.path,
+            "content_type": self.content_type,
+            "size": self.size,
+        })
+    }
 }
 
 impl fmt::Display for Request {
@@ -34,10 +110,111 @@ impl fmt::Display for Request {
         writeln!(f, "Url: {}", &self.url)?;
         writeln!(f, "Version: {:#?}", &self.version)?;
         writeln!(f, "Headers: {:#?}", &self.headers)?;
+
+        let cookies: Vec<&str> = self
+            .headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(';'))
+            .map(str::trim)
+            .filter(|cookie| !cookie.is_empty())
+            .collect();
+
+        if !cookies.is_empty() {
+            writeln!(f, "Cookies:")?;
+            for cookie in cookies {
+                writeln!(f, "    {}", cookie)?;
+            }
+        }
+
         match &self.body {
-            Some(body) => writeln!(f, "Body: {}", body)?,
+            Some(body) => write!(f, "{}", body)?,
+            // HEAD requests and their responses don't have a body
+            None if self.method == Method::HEAD => (),
             None => writeln!(f, "Body: {{}}")?,
         }
         Ok(())
     }
 }
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Json(json) => writeln!(f, "Body: {}", json)?,
+            Body::Text(text) => writeln!(f, "Body: {}", text)?,
+            Body::Form(fields) => {
+                writeln!(f, "Body (form):")?;
+                for (key, value) in fields {
+                    writeln!(f, "    {}: {}", key, value)?;
+                }
+            }
+            Body::Multipart { fields, files } => {
+                writeln!(f, "Body (multipart):")?;
+                for (key, value) in fields {
+                    writeln!(f, "    {}: {}", key, value)?;
+                }
+                for (key, file) in files {
+                    writeln!(f, "    {}: {}", key, file)?;
+                }
+            }
+            Body::File(file) => writeln!(f, "Body (file): {}", file)?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {} bytes)",
+            self.path, self.content_type, self.size
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, body: Option<Body>) -> Request {
+        Request::new(
+            Version::HTTP_11,
+            method,
+            Url::parse("http://localhost/my_endpoint").unwrap(),
+            HeaderMap::new(),
+            body,
+        )
+    }
+
+    #[test]
+    fn test_display() {
+        let formatted = request(Method::POST, Some(Body::Json("{}".to_string()))).to_string();
+        assert!(formatted.starts_with("Method: POST\n"));
+        assert!(formatted.ends_with("Body: {}\n"));
+
+        let body = Body::Multipart {
+            fields: vec![("name".to_string(), "my_name".to_string())],
+            files: vec![(
+                "upload".to_string(),
+                FileInfo {
+                    path: "my_file.png".to_string(),
+                    content_type: "image/png".to_string(),
+                    size: 42,
+                },
+            )],
+        };
+        let formatted = request(Method::POST, Some(body)).to_string();
+        assert!(formatted.ends_with(
+            "Body (multipart):\n    name: my_name\n    upload: my_file.png (image/png, 42 bytes)\n"
+        ));
+
+        let formatted = request(Method::HEAD, None).to_string();
+        assert!(formatted.starts_with("Method: HEAD\n"));
+        assert!(!formatted.contains("Body"));
+
+        let formatted = request(Method::from_bytes(b"PURGE").unwrap(), None).to_string();
+        assert!(formatted.starts_with("Method: PURGE\n"));
+    }
+}
@@ -1,4 +1,9 @@
-use reqwest::{header::HeaderMap, StatusCode, Version};
+use crate::client::output::{Direction, Record};
+use reqwest::{
+    header::{HeaderMap, SET_COOKIE},
+    StatusCode, Version,
+};
+use serde_json::Value;
 use std::fmt;
 
 #[derive(Debug)]
@@ -6,11 +11,16 @@ pub struct Response {
     pub version: Version,
     pub status: StatusCode,
     pub headers: HeaderMap,
-    pub body: String,
+    pub body: Option<String>,
 }
 
 impl Response {
-    pub fn new(version: Version, status: StatusCode, headers: HeaderMap, body: String) -> Self {
+    pub fn new(
+        version: Version,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Option<String>,
+    ) -> Self {
         Self {
             version,
             status,
@@ -18,6 +28,20 @@ impl Response {
             body,
         }
     }
+
+    /// The record of the response, where the body is passed decoded, as the
+    /// body of the response is rendered for display.
+    pub fn to_record(&self, kind: &'static str, body: Option<Value>) -> Record {
+        let record = Record::new("http", Direction::Received, kind)
+            .detail("status", self.status.as_u16())
+            .detail("version", format!("{:?}", self.version))
+            .headers(&self.headers);
+
+        match body {
+            Some(body) => record.body(body),
+            None => record,
+        }
+    }
 }
 
 impl fmt::Display for Response {
@@ -25,7 +49,24 @@ impl fmt::Display for Response {
         writeln!(f, "Status: {}", &self.status)?;
         writeln!(f, "Version: {:#?}", &self.version)?;
         writeln!(f, "Headers: {:#?}", &self.headers)?;
-        writeln!(f, "Body: {}", &self.body)?;
+
+        let cookies: Vec<&str> = self
+            .headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .collect();
+
+        if !cookies.is_empty() {
+            writeln!(f, "Cookies:")?;
+            for cookie in cookies {
+                writeln!(f, "    {}", cookie)?;
+            }
+        }
+
+        if let Some(body) = &self.body {
+            writeln!(f, "Body: {}", body)?;
+        }
         Ok(())
     }
 }
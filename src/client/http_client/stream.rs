@@ -0,0 +1,585 @@
+//! Incremental parsing of streaming responses, i.e. server-sent events (see
+//! https://html.spec.whatwg.org/multipage/server-sent-events.html) and
+//! chunked NDJSON.
+
+use crate::client::{
+    output::{self, Direction, Record},
+    prettify,
+    timeout::{with_timeout, Timeout, TimeoutError},
+};
+use mime::Mime;
+use reqwest::{
+    header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE},
+    Response,
+};
+use std::{fmt, mem, time::Duration};
+
+/// The reconnection time if not set by the server.
+pub const DEFAULT_RETRY: Duration = Duration::from_millis(3000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    EventStream,
+    Ndjson,
+}
+
+/// Detects streaming responses: event streams, and NDJSON responses without
+/// content length.
+pub fn stream_kind(headers: &HeaderMap) -> Option<StreamKind> {
+    let mime = headers
+        .get(CONTENT_TYPE)?
+        .to_str()
+        .ok()?
+        .parse::<Mime>()
+        .ok()?;
+
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("text", "event-stream") => Some(StreamKind::EventStream),
+        ("application", "x-ndjson" | "ndjson" | "jsonl" | "x-jsonlines" | "jsonlines")
+            if !headers.contains_key(CONTENT_LENGTH) =>
+        {
+            Some(StreamKind::Ndjson)
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Event {
+    pub event: Option<String>,
+    pub id: Option<String>,
+    pub retry: Option<u64>,
+    pub data: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Item {
+    Event(Event),
+    Line(String),
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Event(event) => {
+                write!(f, "Event: {}", event.event.as_deref().unwrap_or("message"))?;
+                if let Some(id) = &event.id {
+                    write!(f, "\nId: {}", id)?;
+                }
+                if let Some(retry) = event.retry {
+                    write!(f, "\nRetry: {}", retry)?;
+                }
+                write!(f, "\nData: {}", pretty(&event.data))
+            }
+            Item::Line(line) => write!(f, "Line: {}", pretty(line)),
+        }
+    }
+}
+
+impl Item {
+    pub fn to_record(&self) -> Record {
+        match self {
+            Item::Event(event) => {
+                let mut record = Record::new("http", Direction::Received, "event")
+                    .detail("event", event.event.as_deref().unwrap_or("message"));
+
+                if let Some(id) = &event.id {
+                    record = record.detail("id", id.as_str());
+                }
+
+                if let Some(retry) = event.retry {
+                    record = record.detail("retry", retry);
+                }
+
+                record.body(output::decode_body(event.data.as_bytes()))
+            }
+            Item::Line(line) => Record::new("http", Direction::Received, "line")
+                .body(output::decode_body(line.as_bytes())),
+        }
+    }
+}
+
+/// Pretty-prints json, and leaves other text as is.
+fn pretty(text: &str) -> String {
+    prettify(text).unwrap_or_else(|_| text.to_owned())
+}
+
+/// Splits a byte stream into lines, accepting `\n`, `\r\n`, and `\r` as line
+/// endings.
+#[derive(Default)]
+struct LineBuffer {
+    buffer: Vec<u8>,
+    /// Whether the last chunk ended with `\r`, so that a leading `\n` of the
+    /// next chunk belongs to the same line ending
+    pending_cr: bool,
+}
+
+impl LineBuffer {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+
+        for &byte in chunk {
+            if mem::take(&mut self.pending_cr) && byte == b'\n' {
+                continue;
+            }
+
+            match byte {
+                b'\n' => lines.push(self.take_line()),
+                b'\r' => {
+                    lines.push(self.take_line());
+                    self.pending_cr = true;
+                }
+                _ => self.buffer.push(byte),
+            }
+        }
+
+        lines
+    }
+
+    fn take_line(&mut self) -> String {
+        String::from_utf8_lossy(&mem::take(&mut self.buffer)).into_owned()
+    }
+
+    /// Returns the remaining incomplete line at the end of the stream.
+    fn finish(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            None
+        } else {
+            Some(self.take_line())
+        }
+    }
+}
+
+/// Parses server-sent events chunk by chunk.
+#[derive(Default)]
+struct EventParser {
+    lines: LineBuffer,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<u64>,
+    /// The last event id, which is kept across events and sent as
+    /// `Last-Event-ID` on reconnection
+    last_event_id: Option<String>,
+    /// The reconnection time in milliseconds as last set by the server
+    reconnection_time: Option<u64>,
+}
+
+impl EventParser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.lines
+            .feed(chunk)
+            .into_iter()
+            .filter_map(|line| self.parse_line(&line))
+            .collect()
+    }
+
+    /// Parses the remaining incomplete line at the end of the stream. An
+    /// incomplete event is discarded.
+    fn finish(&mut self) {
+        if let Some(line) = self.lines.finish() {
+            self.parse_line(&line);
+        }
+
+        self.event = None;
+        self.data = None;
+        self.retry = None;
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comments start with a colon
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_owned()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_owned()),
+            },
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_owned()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                    self.reconnection_time = Some(retry);
+                }
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    /// Dispatches the event at the end of a block. Blocks without data are
+    /// not dispatched.
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take();
+        let retry = self.retry.take();
+        let data = self.data.take()?;
+
+        Some(Event {
+            event,
+            id: self.last_event_id.clone(),
+            retry,
+            data,
+        })
+    }
+}
+
+/// Parses the chunks of a streaming response into items.
+pub struct StreamParser {
+    stream_kind: StreamKind,
+    events: EventParser,
+    lines: LineBuffer,
+}
+
+impl StreamParser {
+    pub fn new(stream_kind: StreamKind) -> Self {
+        Self {
+            stream_kind,
+            events: EventParser::default(),
+            lines: LineBuffer::default(),
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Item> {
+        match self.stream_kind {
+            StreamKind::EventStream => self
+                .events
+                .feed(chunk)
+                .into_iter()
+                .map(Item::Event)
+                .collect(),
+            StreamKind::Ndjson => to_lines(self.lines.feed(chunk)),
+        }
+    }
+
+    /// Completes the stream, e.g. before reconnecting. The parsed last event
+    /// id and reconnection time are kept.
+    pub fn finish(&mut self) -> Vec<Item> {
+        match self.stream_kind {
+            StreamKind::EventStream => {
+                self.events.finish();
+                vec![]
+            }
+            StreamKind::Ndjson => to_lines(self.lines.finish().into_iter().collect()),
+        }
+    }
+
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.events.last_event_id.as_deref()
+    }
+
+    pub fn reconnection_time(&self) -> Duration {
+        self.events
+            .reconnection_time
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_RETRY)
+    }
+}
+
+fn to_lines(lines: Vec<String>) -> Vec<Item> {
+    lines
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(Item::Line)
+        .collect()
+}
+
+/// Receives the body of a streaming response chunk by chunk, and passes each
+/// parsed item to `on_item` as soon as it is complete.
+pub async fn receive<F>(
+    mut response: Response,
+    parser: &mut StreamParser,
+    timeout: &Timeout,
+    mut on_item: F,
+) -> Result<(), anyhow::Error>
+where
+    F: FnMut(Item),
+{
+    while let Some(chunk) = with_timeout(timeout.idle(), TimeoutError::Idle, response.chunk())
+        .await?
+        .map_err(|err| super::map_timeout_error(err, timeout))?
+    {
+        for item in parser.feed(&chunk) {
+            on_item(item);
+        }
+    }
+
+    for item in parser.finish() {
+        on_item(item);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::test_server;
+    use hyper::Body;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn event(event: Option<&str>, id: Option<&str>, data: &str) -> Item {
+        Item::Event(Event {
+            event: event.map(ToOwned::to_owned),
+            id: id.map(ToOwned::to_owned),
+            retry: None,
+            data: data.to_owned(),
+        })
+    }
+
+    #[test]
+    fn test_event_parser() {
+        let mut parser = StreamParser::new(StreamKind::EventStream);
+
+        assert_eq!(parser.feed(b": comment\nevent: update\nid: 1\nda"), vec![]);
+        assert_eq!(
+            parser.feed(b"ta: {\"a\":1}\r\n\r\ndata:first\ndata: second\n\nretry: 10\n\n"),
+            vec![
+                event(Some("update"), Some("1"), "{\"a\":1}"),
+                event(None, Some("1"), "first\nsecond"),
+            ]
+        );
+        assert_eq!(parser.last_event_id(), Some("1"));
+        assert_eq!(parser.reconnection_time(), Duration::from_millis(10));
+
+        // Incomplete events are discarded at the end of the stream
+        assert_eq!(parser.feed(b"id: 2\ndata: incomplete\n"), vec![]);
+        assert_eq!(parser.finish(), vec![]);
+        assert_eq!(parser.last_event_id(), Some("2"));
+    }
+
+    #[test]
+    fn test_line_parser() {
+        let mut parser = StreamParser::new(StreamKind::Ndjson);
+
+        assert_eq!(
+            parser.feed(b"{\"a\":1}\n\n{\"b\""),
+            vec![Item::Line("{\"a\":1}".to_owned())]
+        );
+        assert_eq!(parser.finish(), vec![Item::Line("{\"b\"".to_owned())]);
+    }
+
+    #[test]
+    fn test_display() {
+        let item = event(Some("update"), Some("1"), "{\"a\":1}");
+        assert_eq!(
+            item.to_string(),
+            "Event: update\nId: 1\nData: {\n    \"a\": 1\n}"
+        );
+
+        let item = event(None, None, "text");
+        assert_eq!(item.to_string(), "Event: message\nData: text");
+    }
+
+    #[tokio::test]
+    async fn test_receive_event_stream() {
+        let addr = test_server::spawn(false, |_| async {
+            let chunks = vec![
+                "id: 1\ndata: {\"a\":",
+                "1}\n\n",
+                "event: done\ndata: bye\n\n",
+            ];
+            test_server::chunked_response("text/event-stream", chunks)
+        });
+        let response = reqwest::get(format!("http://{}/events", addr))
+            .await
+            .unwrap();
+        let stream_kind = stream_kind(response.headers()).unwrap();
+        let mut parser = StreamParser::new(stream_kind);
+        let mut items = vec![];
+
+        receive(response, &mut parser, &Timeout::default(), |item| {
+            items.push(item)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(stream_kind, StreamKind::EventStream);
+        assert_eq!(
+            items,
+            vec![
+                event(None, Some("1"), "{\"a\":1}"),
+                event(Some("done"), Some("1"), "bye"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receive_ndjson() {
+        let addr = test_server::spawn(false, |_| async {
+            let chunks = vec!["{\"a\":1}\n{\"b\"", ":2}\n"];
+            test_server::chunked_response("application/x-ndjson", chunks)
+        });
+        let response = reqwest::get(format!("http://{}/ndjson", addr))
+            .await
+            .unwrap();
+        let stream_kind = stream_kind(response.headers()).unwrap();
+        let mut parser = StreamParser::new(stream_kind);
+        let mut items = vec![];
+
+        receive(response, &mut parser, &Timeout::default(), |item| {
+            items.push(item)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(stream_kind, StreamKind::Ndjson);
+        assert_eq!(
+            items,
+            vec![
+                Item::Line("{\"a\":1}".to_owned()),
+                Item::Line("{\"b\":2}".to_owned())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let addr = test_server::spawn(false, |_| async {
+            let (sender, body) = Body::channel();
+
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                drop(sender);
+            });
+
+            hyper::Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .body(body)
+                .unwrap()
+        });
+        let response = reqwest::get(format!("http://{}/events", addr))
+            .await
+            .unwrap();
+        let mut parser = StreamParser::new(StreamKind::EventStream);
+        let timeout = Timeout {
+            idle: Some(0.05),
+            ..Timeout::default()
+        };
+
+        let err = receive(response, &mut parser, &timeout, |_| ())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError::Idle(Duration::from_millis(50)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let addr = test_server::spawn(false, |_| async {
+            let (mut sender, body) = Body::channel();
+
+            tokio::spawn(async move {
+                sender.send_data("data: hello\n\n".into()).await.unwrap();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                drop(sender);
+            });
+
+            hyper::Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .body(body)
+                .unwrap()
+        });
+        let config = serde_json::json!({
+            "api": { "url": format!("http://{}", addr), "endpoint": "/events" },
+            "request": { "method": "GET" },
+            "response": { "max_reconnects": 2 },
+            "timeout": { "request": 0.2 }
+        });
+
+        let err = crate::client::http_client::use_http_client(config.to_string())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError::Request(Duration::from_millis(200)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_connection_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(Mutex::new(0));
+        let accepted = connections.clone();
+
+        // The second connection is closed without response
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 1024];
+                let _ = socket.read(&mut request).await;
+
+                *accepted.lock().unwrap() += 1;
+
+                if *accepted.lock().unwrap() != 2 {
+                    let response = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                        connection: close\r\n\r\nretry: 10\ndata: hello\n\n";
+                    let _ = socket.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+        let config = serde_json::json!({
+            "api": { "url": format!("http://{}", addr), "endpoint": "/events" },
+            "request": { "method": "GET" },
+            "response": { "max_reconnects": 2 }
+        });
+
+        crate::client::http_client::use_http_client(config.to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(*connections.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_last_event_id() {
+        let last_event_ids = Arc::new(Mutex::new(vec![]));
+        let received = last_event_ids.clone();
+        let addr = test_server::spawn(false, move |request| {
+            let last_event_id = request
+                .headers()
+                .get("last-event-id")
+                .map(|value| value.to_str().unwrap().to_owned());
+            received.lock().unwrap().push(last_event_id);
+
+            async {
+                let chunks = vec!["retry: 10\nid: 42\ndata: hello\n\n"];
+                test_server::chunked_response("text/event-stream", chunks)
+            }
+        });
+        let config = serde_json::json!({
+            "api": { "url": format!("http://{}", addr), "endpoint": "/events" },
+            "request": { "method": "GET" },
+            "response": { "max_reconnects": 2 }
+        });
+
+        crate::client::http_client::use_http_client(config.to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *last_event_ids.lock().unwrap(),
+            vec![None, Some("42".to_owned()), Some("42".to_owned())]
+        );
+    }
+}
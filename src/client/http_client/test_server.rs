@@ -0,0 +1,112 @@
+//! Local http server for testing the http client.
+
+use hyper::{
+    body::Body,
+    header::CONTENT_TYPE,
+    server::conn::{AddrIncoming, Http},
+    service::{make_service_fn, service_fn},
+    Request, Response, Server,
+};
+use native_tls::{Identity, TlsAcceptor};
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+/// Starts the server on a random local port and returns its address. Each
+/// request is answered by `respond`. The server accepts HTTP/1.1 and HTTP/2
+/// with prior knowledge, or only HTTP/1.1 if `http1_only` is set.
+pub fn spawn<F, Fut>(http1_only: bool, respond: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let make_service = make_service_fn(move |_| {
+        let respond = respond.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let respond = respond.clone();
+                async move { Ok::<_, Infallible>(respond(request).await) }
+            }))
+        }
+    });
+    let incoming =
+        AddrIncoming::from_listener(tokio::net::TcpListener::from_std(listener).unwrap()).unwrap();
+
+    tokio::spawn(
+        Server::builder(incoming)
+            .http1_only(http1_only)
+            .serve(make_service),
+    );
+
+    addr
+}
+
+/// Starts the server with TLS on a random local port and returns its address.
+/// The certificate of `tests/tls` is valid for `localhost`, and HTTP/2 or
+/// HTTP/1.1 is negotiated via ALPN.
+pub fn spawn_tls<F, Fut>(respond: F) -> SocketAddr
+where
+    F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let identity = Identity::from_pkcs8(
+        include_bytes!("../../../tests/tls/server.pem"),
+        include_bytes!("../../../tests/tls/server.key"),
+    )
+    .unwrap();
+    let acceptor = TlsAcceptor::builder(identity)
+        .accept_alpn(&["h2", "http/1.1"])
+        .build()
+        .unwrap();
+    let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            let respond = respond.clone();
+
+            tokio::spawn(async move {
+                let stream = acceptor.accept(stream).await.unwrap();
+                let service = service_fn(move |request| {
+                    let respond = respond.clone();
+                    async move { Ok::<_, Infallible>(respond(request).await) }
+                });
+                let _ = Http::new().serve_connection(stream, service).await;
+            });
+        }
+    });
+
+    addr
+}
+
+/// Creates a response which sends the chunks with a short delay in between.
+pub fn chunked_response(content_type: &str, chunks: Vec<&'static str>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        for chunk in chunks {
+            if sender.send_data(chunk.into()).await.is_err() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+
+    Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(body)
+        .unwrap()
+}
@@ -0,0 +1,298 @@
+//! Interpolation of `${VAR}` and `${VAR:-default}` placeholders in configs.
+//! Variables are resolved from the process environment and an optional
+//! `.env` file, where the environment takes precedence. `$${` is replaced by
+//! a literal `${`.
+
+use anyhow::{anyhow, Context};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, env, fs, path::Path};
+
+/// The optional env file in the current directory
+pub const ENV_FILE: &str = ".env";
+
+/// Loads the variables of the env file, if it exists, and the environment.
+pub fn variables(env_file: &Path) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut variables = if env_file.exists() {
+        let content = fs::read_to_string(env_file)
+            .context(format!("Can't read file '{}'", env_file.display()))?;
+        parse_env_file(&content).context(format!("Can't parse file '{}'", env_file.display()))?
+    } else {
+        HashMap::new()
+    };
+
+    variables.extend(env::vars());
+
+    Ok(variables)
+}
+
+/// Replaces the placeholders in all strings and object keys of the json
+/// value. All unresolved variables are reported with their json path.
+pub fn interpolate(
+    value: Value,
+    variables: &HashMap<String, String>,
+) -> Result<Value, anyhow::Error> {
+    let mut unresolved = vec![];
+    let value = interpolate_value(value, variables, "$", &mut unresolved)?;
+
+    if unresolved.is_empty() {
+        Ok(value)
+    } else {
+        Err(anyhow!("Unresolved variables: {}", unresolved.join(", ")))
+    }
+}
+
+fn interpolate_value(
+    value: Value,
+    variables: &HashMap<String, String>,
+    path: &str,
+    unresolved: &mut Vec<String>,
+) -> Result<Value, anyhow::Error> {
+    let value = match value {
+        Value::String(string) => {
+            Value::String(interpolate_str(&string, variables, path, unresolved)?)
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    interpolate_value(value, variables, &format!("{}[{}]", path, i), unresolved)
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(object) => {
+            let mut interpolated = Map::new();
+
+            for (key, value) in object {
+                let key = interpolate_str(&key, variables, path, unresolved)?;
+                let path = json_path(path, &key);
+                let value = interpolate_value(value, variables, &path, unresolved)?;
+                interpolated.insert(key, value);
+            }
+
+            Value::Object(interpolated)
+        }
+        value => value,
+    };
+
+    Ok(value)
+}
+
+fn interpolate_str(
+    string: &str,
+    variables: &HashMap<String, String>,
+    path: &str,
+    unresolved: &mut Vec<String>,
+) -> Result<String, anyhow::Error> {
+    let mut interpolated = String::new();
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        // An escaped placeholder is kept without the escaping dollar sign
+        if rest[..start].ends_with('$') {
+            interpolated.push_str(&rest[..start - 1]);
+            interpolated.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        interpolated.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated placeholder at {}", path))?;
+        let placeholder = &rest[start..start + end + 1];
+        let expression = &placeholder[2..placeholder.len() - 1];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None),
+        };
+
+        if !is_variable_name(name) {
+            return Err(anyhow!(
+                "Invalid variable name in '{}' at {}",
+                placeholder,
+                path
+            ));
+        }
+
+        match (variables.get(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => interpolated.push_str(default),
+            (Some(value), _) => interpolated.push_str(value),
+            (None, Some(default)) => interpolated.push_str(default),
+            (None, None) => unresolved.push(format!("'{}' at {}", placeholder, path)),
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+        }
+        _ => false,
+    }
+}
+
+fn json_path(path: &str, key: &str) -> String {
+    if is_variable_name(key) {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{:?}]", path, key)
+    }
+}
+
+/// Parses lines of `KEY=value`, optionally prefixed by `export`. Values may be
+/// quoted, and lines starting with `#` are ignored.
+fn parse_env_file(content: &str) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut variables = HashMap::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Missing '=' in line {}", i + 1))?;
+        let name = name.trim();
+
+        if !is_variable_name(name) {
+            return Err(anyhow!(
+                "Invalid variable name '{}' in line {}",
+                name,
+                i + 1
+            ));
+        }
+
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) if value.len() > 1 && value.ends_with(quote) => {
+                &value[1..value.len() - 1]
+            }
+            _ => value,
+        };
+
+        variables.insert(name.to_owned(), value.to_owned());
+    }
+
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("HOST".to_owned(), "localhost".to_owned()),
+            ("TOKEN".to_owned(), "secret".to_owned()),
+            ("EMPTY".to_owned(), "".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let config = json!({
+            "api": { "url": "http://${HOST}:${PORT:-8080}", "endpoint": "/${EMPTY:-items}" },
+            "request": {
+                "headers": [{ "key": "Authorization", "value": "Bearer ${TOKEN}" }],
+                "body": { "${HOST}": ["$${HOST}", 1, null] }
+            }
+        });
+
+        assert_eq!(
+            interpolate(config, &test_variables()).unwrap(),
+            json!({
+                "api": { "url": "http://localhost:8080", "endpoint": "/items" },
+                "request": {
+                    "headers": [{ "key": "Authorization", "value": "Bearer secret" }],
+                    "body": { "localhost": ["${HOST}", 1, null] }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_unresolved() {
+        let config = json!({
+            "api": { "url": "http://${MISSING_HOST}" },
+            "request": { "headers": [{ "key": "x-key", "value": "${MISSING_KEY}" }] },
+            "body": { "my key": "${MISSING_VALUE}" }
+        });
+
+        assert_eq!(
+            interpolate(config, &test_variables())
+                .unwrap_err()
+                .to_string(),
+            "Unresolved variables: '${MISSING_HOST}' at $.api.url, \
+            '${MISSING_VALUE}' at $.body[\"my key\"], \
+            '${MISSING_KEY}' at $.request.headers[0].value"
+        );
+    }
+
+    #[test]
+    fn test_invalid_placeholder() {
+        assert_eq!(
+            interpolate(json!({ "url": "${HOST" }), &test_variables())
+                .unwrap_err()
+                .to_string(),
+            "Unterminated placeholder at $.url"
+        );
+        assert_eq!(
+            interpolate(json!({ "url": "${1HOST}" }), &test_variables())
+                .unwrap_err()
+                .to_string(),
+            "Invalid variable name in '${1HOST}' at $.url"
+        );
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content =
+            "# comment\nHOST=localhost\nexport TOKEN=\"my secret\"\n\nEMPTY=\nQUOTE='a=b'\n";
+
+        assert_eq!(
+            parse_env_file(content).unwrap(),
+            HashMap::from([
+                ("HOST".to_owned(), "localhost".to_owned()),
+                ("TOKEN".to_owned(), "my secret".to_owned()),
+                ("EMPTY".to_owned(), "".to_owned()),
+                ("QUOTE".to_owned(), "a=b".to_owned()),
+            ])
+        );
+        assert_eq!(
+            parse_env_file("HOST").unwrap_err().to_string(),
+            "Missing '=' in line 1"
+        );
+    }
+
+    #[test]
+    fn test_variables_from_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(
+            &path,
+            "ANY_CLIENT_TEST_VARIABLE=from_file\nPATH=from_file\n",
+        )
+        .unwrap();
+
+        let variables = variables(&path).unwrap();
+
+        assert_eq!(variables["ANY_CLIENT_TEST_VARIABLE"], "from_file");
+        // The environment takes precedence
+        assert_eq!(variables["PATH"], env::var("PATH").unwrap());
+    }
+}
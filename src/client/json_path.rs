@@ -0,0 +1,205 @@
+//! A subset of JSONPath to select values from json responses:
+//!
+//! - `$`: the root value
+//! - `.key` or `['key']`: the value of a key
+//! - `[0]`: an array element, where negative indices count from the end
+//! - `.*` or `[*]`: all elements of an array or object
+//! - `..key`: the values of a key at any depth
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Descendant(String),
+}
+
+/// Selects the values matching the path.
+pub fn select<'a>(value: &'a Value, path: &str) -> Result<Vec<&'a Value>, anyhow::Error> {
+    let segments = parse(path).map_err(|err| anyhow!("Invalid json path '{}': {}", path, err))?;
+    let mut values = vec![value];
+
+    for segment in &segments {
+        values = values
+            .into_iter()
+            .flat_map(|value| select_segment(value, segment))
+            .collect();
+    }
+
+    Ok(values)
+}
+
+fn select_segment<'a>(value: &'a Value, segment: &Segment) -> Vec<&'a Value> {
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(object)) => object.get(key).into_iter().collect(),
+        (Segment::Index(index), Value::Array(array)) => {
+            let index = if *index < 0 {
+                array.len() as i64 + index
+            } else {
+                *index
+            };
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| array.get(index))
+                .into_iter()
+                .collect()
+        }
+        (Segment::Wildcard, Value::Array(array)) => array.iter().collect(),
+        (Segment::Wildcard, Value::Object(object)) => object.values().collect(),
+        (Segment::Descendant(key), _) => {
+            let mut values = vec![];
+            collect_descendants(value, key, &mut values);
+            values
+        }
+        _ => vec![],
+    }
+}
+
+fn collect_descendants<'a>(value: &'a Value, key: &str, values: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(value) = object.get(key) {
+                values.push(value);
+            }
+
+            for value in object.values() {
+                collect_descendants(value, key, values);
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                collect_descendants(value, key, values);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn parse(path: &str) -> Result<Vec<Segment>, String> {
+    let mut rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| "expected '$' at start".to_owned())?;
+    let mut segments = vec![];
+
+    while !rest.is_empty() {
+        if let Some(descendant) = rest.strip_prefix("..") {
+            let (key, remaining) = split_key(descendant);
+
+            if key.is_empty() {
+                return Err("expected key after '..'".to_owned());
+            }
+
+            segments.push(Segment::Descendant(key.to_owned()));
+            rest = remaining;
+        } else if let Some(child) = rest.strip_prefix('.') {
+            let (key, remaining) = split_key(child);
+
+            match key {
+                "" => return Err("expected key after '.'".to_owned()),
+                "*" => segments.push(Segment::Wildcard),
+                key => segments.push(Segment::Key(key.to_owned())),
+            }
+
+            rest = remaining;
+        } else if let Some(bracket) = rest.strip_prefix('[') {
+            let end = bracket.find(']').ok_or_else(|| "expected ']'".to_owned())?;
+            let selector = bracket[..end].trim();
+
+            let segment = match selector {
+                "*" => Segment::Wildcard,
+                selector if is_quoted(selector) => {
+                    Segment::Key(selector[1..selector.len() - 1].to_owned())
+                }
+                selector => Segment::Index(
+                    selector
+                        .parse()
+                        .map_err(|_| format!("invalid selector '{}'", selector))?,
+                ),
+            };
+
+            segments.push(segment);
+            rest = &bracket[end + 1..];
+        } else {
+            return Err(format!("unexpected '{}'", rest));
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Splits the key of a dot notation from the remaining path.
+fn split_key(path: &str) -> (&str, &str) {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    path.split_at(end)
+}
+
+fn is_quoted(selector: &str) -> bool {
+    selector.len() >= 2
+        && ((selector.starts_with('\'') && selector.ends_with('\''))
+            || (selector.starts_with('"') && selector.ends_with('"')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response() -> Value {
+        json!({
+            "token": "abc",
+            "user": { "id": 1, "roles": ["admin", "dev"] },
+            "items": [
+                { "id": 10, "name": "first" },
+                { "id": 20, "name": "second", "tags": { "id": 30 } }
+            ],
+            "my key": true
+        })
+    }
+
+    #[test]
+    fn test_select() {
+        let response = response();
+        let cases = [
+            ("$", vec![response.to_owned()]),
+            ("$.token", vec![json!("abc")]),
+            ("$.user.roles[1]", vec![json!("dev")]),
+            ("$.user.roles[-1]", vec![json!("dev")]),
+            ("$['my key']", vec![json!(true)]),
+            ("$.items[*].id", vec![json!(10), json!(20)]),
+            ("$.items.*.name", vec![json!("first"), json!("second")]),
+            ("$..id", vec![json!(10), json!(20), json!(30), json!(1)]),
+            ("$.missing", vec![]),
+            ("$.user.roles[5]", vec![]),
+        ];
+
+        for (path, expected) in cases {
+            let values: Vec<Value> = select(&response, path)
+                .unwrap()
+                .into_iter()
+                .cloned()
+                .collect();
+            assert_eq!(values, expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_invalid_path() {
+        let response = response();
+
+        assert_eq!(
+            select(&response, "token").unwrap_err().to_string(),
+            "Invalid json path 'token': expected '$' at start"
+        );
+        assert_eq!(
+            select(&response, "$.items[x]").unwrap_err().to_string(),
+            "Invalid json path '$.items[x]': invalid selector 'x'"
+        );
+        assert_eq!(
+            select(&response, "$.items[0").unwrap_err().to_string(),
+            "Invalid json path '$.items[0': expected ']'"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! A subset of JSON Schema to validate json responses. Supported keywords
+//! are `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `minItems`, `maxItems`, `minLength`,
+//! `maxLength`, `minimum`, and `maximum`. Schemas with other keywords are
+//! rejected, except for annotations like `title` and `description`.
+
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+const KEYWORDS: [&str; 13] = [
+    "type",
+    "enum",
+    "const",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "minItems",
+    "maxItems",
+    "minLength",
+    "maxLength",
+    "minimum",
+    "maximum",
+];
+
+/// Keywords which don't affect validation
+const ANNOTATIONS: [&str; 8] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+];
+
+/// Checks that the schema only uses supported keywords, so that it isn't
+/// passed without checking the unsupported ones.
+pub fn check(schema: &Value) -> Result<(), anyhow::Error> {
+    check_schema(schema, "#")
+}
+
+fn check_schema(schema: &Value, path: &str) -> Result<(), anyhow::Error> {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(_) => return Ok(()),
+        _ => return Err(anyhow!("Invalid json schema at '{}'", path)),
+    };
+
+    for (keyword, value) in schema {
+        let path = format!("{}/{}", path, keyword);
+
+        match keyword.as_str() {
+            "properties" => {
+                let properties = value
+                    .as_object()
+                    .ok_or_else(|| anyhow!("Invalid json schema at '{}'", path))?;
+
+                for (key, property) in properties {
+                    check_schema(property, &format!("{}/{}", path, key))?;
+                }
+            }
+            "items" | "additionalProperties" => check_schema(value, &path)?,
+            keyword if KEYWORDS.contains(&keyword) || ANNOTATIONS.contains(&keyword) => (),
+            keyword => {
+                return Err(anyhow!(
+                    "Unsupported json schema keyword '{}' at '{}'",
+                    keyword,
+                    path
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates the value against the schema, and returns the violations with
+/// the json path of the invalid value.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut violations = vec![];
+    validate_value(value, schema, "$", &mut violations);
+    violations
+}
+
+fn validate_value(value: &Value, schema: &Value, path: &str, violations: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        // The schema `false` rejects every value, and `true` accepts every
+        // value
+        Value::Bool(false) => {
+            violations.push(format!("{}: unexpected value", path));
+            return;
+        }
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types = match expected {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            expected => expected.as_str().into_iter().collect::<Vec<_>>(),
+        };
+
+        if !types.iter().any(|expected| is_type(value, expected)) {
+            violations.push(format!(
+                "{}: expected type {}, got {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        if !values.contains(value) {
+            violations.push(format!(
+                "{}: expected one of {}, got {}",
+                path,
+                Value::Array(values.to_owned()),
+                value
+            ));
+        }
+    }
+
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violations.push(format!("{}: expected {}, got {}", path, expected, value));
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(object, schema, path, violations),
+        Value::Array(array) => {
+            check_bound(schema, "minItems", array.len(), path, violations);
+            check_bound(schema, "maxItems", array.len(), path, violations);
+
+            if let Some(items) = schema.get("items") {
+                for (i, value) in array.iter().enumerate() {
+                    validate_value(value, items, &format!("{}[{}]", path, i), violations);
+                }
+            }
+        }
+        Value::String(string) => {
+            let length = string.chars().count();
+            check_bound(schema, "minLength", length, path, violations);
+            check_bound(schema, "maxLength", length, path, violations);
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+
+            if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+                if number < minimum {
+                    violations.push(format!(
+                        "{}: expected minimum {}, got {}",
+                        path, minimum, number
+                    ));
+                }
+            }
+
+            if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+                if number > maximum {
+                    violations.push(format!(
+                        "{}: expected maximum {}, got {}",
+                        path, maximum, number
+                    ));
+                }
+            }
+        }
+        Value::Bool(_) | Value::Null => (),
+    }
+}
+
+fn validate_object(
+    object: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                violations.push(format!("{}: missing property '{}'", path, key));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    for (key, value) in object {
+        let path = format!("{}.{}", path, key);
+
+        match properties.and_then(|properties| properties.get(key)) {
+            Some(property) => validate_value(value, property, &path, violations),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    violations.push(format!("{}: unexpected property", path))
+                }
+                Some(additional) => validate_value(value, additional, &path, violations),
+                None => (),
+            },
+        }
+    }
+}
+
+/// Checks the `min*` and `max*` keywords for lengths and sizes.
+fn check_bound(
+    schema: &Map<String, Value>,
+    keyword: &str,
+    actual: usize,
+    path: &str,
+    violations: &mut Vec<String>,
+) {
+    let bound = match schema.get(keyword).and_then(Value::as_u64) {
+        Some(bound) => bound as usize,
+        None => return,
+    };
+
+    let is_valid = if keyword.starts_with("min") {
+        actual >= bound
+    } else {
+        actual <= bound
+    };
+
+    if !is_valid {
+        violations.push(format!(
+            "{}: expected {} {}, got {}",
+            path, keyword, bound, actual
+        ));
+    }
+}
+
+fn is_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "integer" => match value {
+            Value::Number(number) => number.as_f64().is_some_and(|number| number.fract() == 0.0),
+            _ => false,
+        },
+        expected => type_name(value) == expected,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {
+                "id": { "type": "integer", "minimum": 1 },
+                "name": { "type": "string", "minLength": 1 },
+                "status": { "enum": ["active", "inactive"] },
+                "tags": { "type": "array", "items": { "type": "string" }, "maxItems": 2 },
+                "parent": { "type": ["object", "null"] }
+            },
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&schema()).is_ok());
+        assert!(check(&json!({ "title": "user", "items": true })).is_ok());
+
+        assert_eq!(
+            check(&json!({ "properties": { "name": { "type": "string", "pattern": "^a" } } }))
+                .unwrap_err()
+                .to_string(),
+            "Unsupported json schema keyword 'pattern' at '#/properties/name/pattern'"
+        );
+        assert_eq!(
+            check(&json!({ "oneOf": [{ "type": "string" }, { "type": "null" }] }))
+                .unwrap_err()
+                .to_string(),
+            "Unsupported json schema keyword 'oneOf' at '#/oneOf'"
+        );
+        assert_eq!(
+            check(&json!({ "items": [{ "type": "string" }] }))
+                .unwrap_err()
+                .to_string(),
+            "Invalid json schema at '#/items'"
+        );
+    }
+
+    #[test]
+    fn test_valid() {
+        let value = json!({
+            "id": 1,
+            "name": "first",
+            "status": "active",
+            "tags": ["a", "b"],
+            "parent": null
+        });
+
+        assert_eq!(validate(&value, &schema()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let value = json!({
+            "id": 0,
+            "name": "",
+            "status": "deleted",
+            "tags": ["a", 1, "c"],
+            "parent": [],
+            "extra": true
+        });
+
+        assert_eq!(
+            validate(&value, &schema()),
+            vec![
+                "$.extra: unexpected property",
+                "$.id: expected minimum 1, got 0",
+                "$.name: expected minLength 1, got 0",
+                "$.parent: expected type object or null, got array",
+                "$.status: expected one of [\"active\",\"inactive\"], got \"deleted\"",
+                "$.tags: expected maxItems 2, got 3",
+                "$.tags[1]: expected type string, got number",
+            ]
+        );
+        assert_eq!(
+            validate(&json!([]), &schema()),
+            vec!["$: expected type object, got array"]
+        );
+        assert_eq!(
+            validate(&json!({}), &schema()),
+            vec!["$: missing property 'id'", "$: missing property 'name'"]
+        );
+    }
+}
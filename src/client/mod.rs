@@ -1,14 +1,28 @@
+pub mod auth;
 pub mod client_type;
+pub mod collection;
+pub mod environment;
+pub mod expect;
 pub mod grpc_client;
 pub mod http_client;
+pub mod interpolation;
+pub mod json_path;
+pub mod json_schema;
+pub mod outcome;
+pub mod output;
+pub mod timeout;
 pub mod websocket_client;
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{io, string::FromUtf8Error};
 use thiserror::Error;
 
-use self::client_type::ClientType;
+use self::{
+    client_type::ClientType, grpc_client::use_grpc_client, http_client::use_http_client,
+    outcome::Outcome, websocket_client::use_websocket_client,
+};
 
 #[derive(Deserialize)]
 pub struct Client {
@@ -16,6 +30,22 @@ pub struct Client {
     pub client_type: ClientType,
 }
 
+/// Runs the client selected by the `client` field of the config. Messages of
+/// streaming clients are only kept for the outcome with `keep_messages`.
+pub async fn use_client(
+    config_file: String,
+    keep_messages: bool,
+) -> Result<Outcome, anyhow::Error> {
+    let client: Client =
+        serde_json::from_str(&config_file).context("Can't deserialize client value")?;
+
+    match client.client_type {
+        ClientType::Http => use_http_client(config_file).await,
+        ClientType::Websocket => use_websocket_client(config_file, keep_messages).await,
+        ClientType::Grpc => use_grpc_client(config_file, keep_messages).await,
+    }
+}
+
 pub fn prettify(obj: &str) -> Result<String, JsonError> {
     let obj: Value = serde_json::from_str(obj).map_err(|err| JsonError::DeserializeError {
         err: err.to_string(),
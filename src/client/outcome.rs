@@ -0,0 +1,109 @@
+use crate::client::expect::Assertion;
+use reqwest::{header::HeaderMap, StatusCode};
+use serde_json::Value;
+use std::time::Duration;
+use tonic::Status;
+
+/// The response of a request, from which values are extracted in
+/// collections and against which expectations are checked.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The status and headers of the response, and the body of a
+    /// non-streaming response, or null otherwise
+    Http {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Value,
+    },
+    /// The received text and binary messages, and the results of the
+    /// scripted steps
+    Websocket {
+        messages: Vec<ReceivedMessage>,
+        steps: Vec<Assertion>,
+    },
+    /// The final status and the response messages
+    Grpc {
+        status: Status,
+        messages: Vec<Value>,
+    },
+}
+
+/// A websocket message and the time it was received after connecting.
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    pub payload: Value,
+    pub elapsed: Duration,
+}
+
+impl Outcome {
+    /// The json value json paths are applied to: the response body for http,
+    /// and the messages for websocket and grpc, where a single message is
+    /// used as is, and several messages as array.
+    pub fn body(&self) -> Value {
+        let messages = match self {
+            Outcome::Http { body, .. } => return body.to_owned(),
+            Outcome::Websocket { messages, .. } => messages
+                .iter()
+                .map(|message| message.payload.to_owned())
+                .collect(),
+            Outcome::Grpc { messages, .. } => messages.to_vec(),
+        };
+
+        match <[Value; 1]>::try_from(messages) {
+            Ok([message]) => message,
+            Err(messages) => Value::Array(messages),
+        }
+    }
+}
+
+/// Parses the payload as json, or falls back to a json string.
+pub fn parse_payload(payload: &[u8]) -> Value {
+    serde_json::from_slice(payload)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(payload).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_body() {
+        let body = Outcome::Http {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: json!({ "token": "abc" }),
+        }
+        .body();
+        assert_eq!(body, json!({ "token": "abc" }));
+
+        let body = Outcome::Grpc {
+            status: Status::new(tonic::Code::Ok, ""),
+            messages: vec![json!({ "id": 1 })],
+        }
+        .body();
+        assert_eq!(body, json!({ "id": 1 }));
+
+        let body = Outcome::Websocket {
+            messages: vec![
+                ReceivedMessage {
+                    payload: json!(1),
+                    elapsed: Duration::from_secs(1),
+                },
+                ReceivedMessage {
+                    payload: json!("text"),
+                    elapsed: Duration::from_secs(2),
+                },
+            ],
+            steps: vec![],
+        }
+        .body();
+        assert_eq!(body, json!([1, "text"]));
+    }
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!(parse_payload(br#"{"id":1}"#), json!({ "id": 1 }));
+        assert_eq!(parse_payload(b"plain text"), json!("plain text"));
+    }
+}
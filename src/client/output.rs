@@ -0,0 +1,184 @@
+//! The output format of requests, responses, and messages. In the text
+//! format, they are printed for humans, partially as log messages. In the
+//! json formats, each of them is printed as a json record on stdout, while
+//! log messages are kept on stderr.
+
+use clap::ValueEnum;
+use http::HeaderMap;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    io::{self, Write},
+    sync::OnceLock,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A pretty-printed json object per record
+    Json,
+    /// A json object per record and line
+    Ndjson,
+}
+
+/// Sets the output format of the process, which can only be set once.
+pub fn set_format(format: Format) {
+    FORMAT.get_or_init(|| format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+pub fn is_text() -> bool {
+    format() == Format::Text
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A request, response, or message in the json formats.
+#[derive(Serialize, Debug)]
+pub struct Record {
+    pub timestamp: String,
+    pub client: &'static str,
+    pub direction: Direction,
+    pub kind: &'static str,
+    /// Fields depending on the kind, e.g. the method and url of a request
+    #[serde(flatten)]
+    pub details: Map<String, Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+impl Record {
+    pub fn new(client: &'static str, direction: Direction, kind: &'static str) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            client,
+            direction,
+            kind,
+            details: Map::new(),
+            headers: None,
+            body: None,
+        }
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_owned(), value.into());
+        self
+    }
+
+    /// Adds the headers as object, where a header with several values has an
+    /// array of values.
+    pub fn headers(mut self, headers: &HeaderMap) -> Self {
+        let mut object = Map::new();
+
+        for name in headers.keys() {
+            let mut values: Vec<Value> = headers
+                .get_all(name)
+                .iter()
+                .map(|value| Value::String(String::from_utf8_lossy(value.as_bytes()).into_owned()))
+                .collect();
+            let value = if values.len() == 1 {
+                values.remove(0)
+            } else {
+                Value::Array(values)
+            };
+            object.insert(name.to_string(), value);
+        }
+
+        self.headers = Some(Value::Object(object));
+        self
+    }
+
+    pub fn body(mut self, body: Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+}
+
+/// Decodes the body as json, or as text, or encodes binary data as base64.
+pub fn decode_body(body: &[u8]) -> Value {
+    if let Ok(value) = serde_json::from_slice(body) {
+        return value;
+    }
+
+    match std::str::from_utf8(body) {
+        Ok(text) => Value::String(text.to_owned()),
+        Err(_) => serde_json::json!({ "base64": base64::encode(body) }),
+    }
+}
+
+/// Prints the record on stdout in the json formats.
+pub fn emit(record: Record) {
+    let record = match format() {
+        Format::Text => return,
+        Format::Json => serde_json::to_string_pretty(&record),
+        Format::Ndjson => serde_json::to_string(&record),
+    };
+
+    match record {
+        Ok(record) => {
+            // Writing fails if stdout is closed, e.g. by `head`
+            let _ = writeln!(io::stdout().lock(), "{}", record);
+        }
+        Err(err) => log::error!("Can't serialize record: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{HeaderValue, CONTENT_TYPE, SET_COOKIE};
+    use serde_json::json;
+
+    #[test]
+    fn test_record() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+
+        let record = Record::new("http", Direction::Received, "response")
+            .detail("status", 200)
+            .headers(&headers)
+            .body(json!({ "id": 1 }));
+        let mut value = serde_json::to_value(&record).unwrap();
+
+        assert!(OffsetDateTime::parse(value["timestamp"].as_str().unwrap(), &Rfc3339).is_ok());
+
+        value.as_object_mut().unwrap().remove("timestamp");
+        assert_eq!(
+            value,
+            json!({
+                "client": "http",
+                "direction": "received",
+                "kind": "response",
+                "status": 200,
+                "headers": { "content-type": "application/json", "set-cookie": ["a=1", "b=2"] },
+                "body": { "id": 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_body() {
+        assert_eq!(decode_body(br#"{"id":1}"#), json!({ "id": 1 }));
+        assert_eq!(decode_body(b"plain text"), json!("plain text"));
+        assert_eq!(decode_body(&[0xff, 0x00]), json!({ "base64": "/wA=" }));
+    }
+}
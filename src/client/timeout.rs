@@ -0,0 +1,170 @@
+use serde::{de, Deserialize, Deserializer};
+use std::{future::Future, time::Duration};
+use thiserror::Error;
+
+/// Timeouts in seconds. Not every client supports every timeout: the idle
+/// timeout only applies to the websocket client and streaming http
+/// responses, and the request timeout to the http and grpc clients.
+#[derive(Deserialize, Default)]
+pub struct Timeout {
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub connect: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub request: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub idle: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub run: Option<f64>,
+}
+
+impl Timeout {
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect.map(Duration::from_secs_f64)
+    }
+
+    pub fn request(&self) -> Option<Duration> {
+        self.request.map(Duration::from_secs_f64)
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle.map(Duration::from_secs_f64)
+    }
+
+    pub fn run(&self) -> Option<Duration> {
+        self.run.map(Duration::from_secs_f64)
+    }
+}
+
+/// Deserializes seconds, which must be convertible to a `Duration`, i.e. not
+/// negative and not too large.
+pub fn deserialize_seconds<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let seconds = f64::deserialize(deserializer)?;
+
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|err| de::Error::custom(format!("invalid seconds {}: {}", seconds, err)))?;
+
+    Ok(seconds)
+}
+
+pub fn deserialize_optional_seconds<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Seconds(#[serde(deserialize_with = "deserialize_seconds")] f64);
+
+    let seconds = Option::<Seconds>::deserialize(deserializer)?;
+    Ok(seconds.map(|Seconds(seconds)| seconds))
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TimeoutError {
+    #[error("Connect timeout expired after {0:?}")]
+    Connect(Duration),
+    #[error("Request timeout expired after {0:?}")]
+    Request(Duration),
+    #[error("Idle timeout expired after {0:?} without receiving a message")]
+    Idle(Duration),
+    #[error("Run duration expired after {0:?}")]
+    Run(Duration),
+}
+
+impl TimeoutError {
+    /// The exit code of the process if the timeout fires.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Connect(_) => 3,
+            Self::Request(_) => 4,
+            Self::Idle(_) => 5,
+            Self::Run(_) => 6,
+        }
+    }
+}
+
+/// Awaits the future, or fails with the given timeout error if the duration
+/// expires first. Without duration, the future is awaited as is.
+pub async fn with_timeout<F>(
+    duration: Option<Duration>,
+    timeout_error: fn(Duration) -> TimeoutError,
+    future: F,
+) -> Result<F::Output, TimeoutError>
+where
+    F: Future,
+{
+    match duration {
+        Some(duration) => tokio::time::timeout(duration, future)
+            .await
+            .map_err(|_| timeout_error(duration)),
+        None => Ok(future.await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deserialize() {
+        let timeout: Timeout =
+            serde_json::from_value(json!({ "connect": 1.5, "run": 60 })).unwrap();
+
+        assert_eq!(timeout.connect(), Some(Duration::from_millis(1500)));
+        assert_eq!(timeout.request(), None);
+        assert_eq!(timeout.idle(), None);
+        assert_eq!(timeout.run(), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        for timeout in [json!({ "idle": -1 }), json!({ "run": 1e300 })] {
+            let err = serde_json::from_value::<Timeout>(timeout).err().unwrap();
+            assert!(err.to_string().starts_with("invalid seconds"), "{}", err);
+        }
+
+        let timeout: Timeout = serde_json::from_value(json!({ "idle": null })).unwrap();
+        assert_eq!(timeout.idle(), None);
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let duration = Duration::from_millis(10);
+
+        assert_eq!(
+            with_timeout(Some(duration), TimeoutError::Idle, async { 1 }).await,
+            Ok(1)
+        );
+        assert_eq!(
+            with_timeout(None, TimeoutError::Idle, tokio::time::sleep(duration)).await,
+            Ok(())
+        );
+        assert_eq!(
+            with_timeout(
+                Some(duration),
+                TimeoutError::Idle,
+                tokio::time::sleep(Duration::from_secs(10))
+            )
+            .await,
+            Err(TimeoutError::Idle(duration))
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let duration = Duration::from_secs(1);
+        let exit_codes = [
+            TimeoutError::Connect(duration).exit_code(),
+            TimeoutError::Request(duration).exit_code(),
+            TimeoutError::Idle(duration).exit_code(),
+            TimeoutError::Run(duration).exit_code(),
+        ];
+
+        for (i, exit_code) in exit_codes.iter().enumerate() {
+            assert!(*exit_code > 1);
+            assert!(!exit_codes[i + 1..].contains(exit_code));
+        }
+    }
+}
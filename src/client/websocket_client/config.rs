@@ -1,11 +1,26 @@
+use crate::client::{
+    auth::Auth,
+    expect::Expect,
+    timeout::{deserialize_optional_seconds, deserialize_seconds, Timeout},
+};
 use serde::Deserialize;
 use serde_json::Value;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub api: Api,
     pub subscription: Subscription,
+    pub auth: Option<Auth>,
+    pub expect: Option<Expect>,
+    /// Whether to send lines of stdin as messages
+    #[serde(default)]
+    pub interactive: bool,
+    /// Whether to reconnect if the connection drops
+    pub reconnect: Option<Reconnect>,
+    #[serde(default)]
+    pub timeout: Timeout,
 }
 
 #[derive(Deserialize)]
@@ -17,5 +32,150 @@ pub struct Api {
 #[derive(Deserialize)]
 pub struct Subscription {
     pub request: Option<Value>,
+    /// Further subscription messages, sent after the request
+    #[serde(default)]
+    pub requests: Vec<Value>,
     pub query_string: Option<Value>,
+    /// Steps executed in order after the subscription request is sent
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+impl Subscription {
+    /// The subscription messages, which are sent on every connect.
+    pub fn requests(&self) -> impl Iterator<Item = &Value> {
+        self.request.iter().chain(&self.requests)
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    /// Sends a json message, where strings are sent as raw text
+    Send(Value),
+    /// Waits for messages matching a json value
+    Expect(ExpectStep),
+    /// Waits for the given seconds
+    Sleep(#[serde(deserialize_with = "deserialize_seconds")] f64),
+    /// Closes the connection, with code 1000 (normal closure) by default
+    Close(Option<CloseStep>),
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ExpectStep {
+    /// The message must contain the given fields, see `expect::is_match`
+    pub matches: Value,
+    /// The number of matching messages
+    #[serde(default = "default_count")]
+    pub count: usize,
+    /// Seconds to wait for the matching messages
+    #[serde(default, deserialize_with = "deserialize_optional_seconds")]
+    pub timeout: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct CloseStep {
+    pub code: Option<u16>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+/// The reconnect policy with exponential backoff in seconds: the delay before
+/// the first attempt is `backoff`, and doubles with every further attempt up
+/// to `max_backoff`.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Reconnect {
+    /// The attempts after each disconnect, or unlimited attempts. With `0`,
+    /// the client doesn't reconnect.
+    pub max_attempts: Option<u32>,
+    #[serde(default = "default_backoff", deserialize_with = "deserialize_seconds")]
+    pub backoff: f64,
+    #[serde(
+        default = "default_max_backoff",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub max_backoff: f64,
+}
+
+impl Reconnect {
+    pub fn is_enabled(&self) -> bool {
+        self.max_attempts != Some(0)
+    }
+
+    /// The delay before the attempt, starting with attempt 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2_f64.powi(attempt.saturating_sub(1).min(32) as i32);
+
+        Duration::from_secs_f64((self.backoff * factor).min(self.max_backoff))
+    }
+
+    pub fn is_exhausted(&self, attempt: u32) -> bool {
+        self.max_attempts
+            .is_some_and(|max_attempts| attempt >= max_attempts)
+    }
+}
+
+fn default_backoff() -> f64 {
+    1.0
+}
+
+fn default_max_backoff() -> f64 {
+    30.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_requests() {
+        let subscription: Subscription = serde_json::from_value(json!({
+            "request": { "channel": "a" },
+            "requests": [{ "channel": "b" }, [{ "batch": 1 }, { "batch": 2 }]]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            subscription.requests().collect::<Vec<_>>(),
+            vec![
+                &json!({ "channel": "a" }),
+                &json!({ "channel": "b" }),
+                &json!([{ "batch": 1 }, { "batch": 2 }])
+            ]
+        );
+
+        let subscription: Subscription = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(subscription.requests().count(), 0);
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let reconnect: Reconnect =
+            serde_json::from_value(json!({ "max_attempts": 3, "max_backoff": 5 })).unwrap();
+
+        assert_eq!(reconnect.delay(1), Duration::from_secs(1));
+        assert_eq!(reconnect.delay(2), Duration::from_secs(2));
+        assert_eq!(reconnect.delay(3), Duration::from_secs(4));
+        assert_eq!(reconnect.delay(4), Duration::from_secs(5));
+        assert_eq!(reconnect.delay(100), Duration::from_secs(5));
+        assert!(!reconnect.is_exhausted(2));
+        assert!(reconnect.is_exhausted(3));
+
+        let reconnect: Reconnect = serde_json::from_value(json!({})).unwrap();
+        assert!(reconnect.is_enabled());
+        assert!(!reconnect.is_exhausted(100));
+
+        let reconnect: Reconnect = serde_json::from_value(json!({ "max_attempts": 0 })).unwrap();
+        assert!(!reconnect.is_enabled());
+
+        for reconnect in [json!({ "backoff": -1 }), json!({ "max_backoff": 1e300 })] {
+            let err = serde_json::from_value::<Reconnect>(reconnect).unwrap_err();
+            assert!(err.to_string().starts_with("invalid seconds"), "{}", err);
+        }
+    }
 }
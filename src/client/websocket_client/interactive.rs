@@ -0,0 +1,136 @@
+//! Interactive mode, where lines of stdin are sent as messages while
+//! received messages are printed. A line is sent as text message, e.g. json
+//! or raw text, unless it is one of the commands:
+//!
+//! - `/binary <text>`: sends the text as binary message
+//! - `/ping [text]`: sends a ping with optional payload
+//! - `/close [code] [reason]`: closes the connection, with code 1000 (normal
+//!   closure) by default
+//!
+//! A line starting with `//` is sent as text starting with `/`.
+
+use anyhow::{anyhow, Context};
+use std::future;
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
+use tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+};
+
+pub const COMMANDS: &str = "/binary <text>, /ping [text], /close [code] [reason]";
+
+/// The lines of stdin, or of another reader in tests.
+pub type Input = Lines<Box<dyn AsyncBufRead + Send + Unpin>>;
+
+pub fn input() -> Input {
+    let reader: Box<dyn AsyncBufRead + Send + Unpin> = Box::new(BufReader::new(io::stdin()));
+    reader.lines()
+}
+
+/// Reads the next line of the input, or waits forever without input.
+pub async fn next_line(input: &mut Option<Input>) -> Result<Option<String>, anyhow::Error> {
+    match input {
+        Some(input) => input.next_line().await.context("Can't read stdin"),
+        None => future::pending().await,
+    }
+}
+
+/// Parses the line into the message to send. Empty lines are skipped.
+pub fn parse(line: &str) -> Result<Option<Message>, anyhow::Error> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    if line.starts_with("//") {
+        return Ok(Some(Message::Text(line[1..].to_owned())));
+    }
+
+    let command = match line.strip_prefix('/') {
+        Some(command) => command,
+        None => return Ok(Some(Message::Text(line.to_owned()))),
+    };
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+
+    let message = match name {
+        "binary" => Message::Binary(argument.as_bytes().to_vec()),
+        "ping" => Message::Ping(argument.as_bytes().to_vec()),
+        "close" => {
+            let argument = argument.trim();
+            let (code, reason) = argument.split_once(' ').unwrap_or((argument, ""));
+            let code = match code {
+                "" => CloseCode::Normal,
+                code => CloseCode::from(
+                    code.parse::<u16>()
+                        .map_err(|_| anyhow!("Invalid close code '{}'", code))?,
+                ),
+            };
+
+            Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.trim().to_owned().into(),
+            }))
+        }
+        name => {
+            return Err(anyhow!(
+                "Unknown command '/{}', expected one of: {}",
+                name,
+                COMMANDS
+            ))
+        }
+    };
+
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r#"{"type": "subscribe"}"#).unwrap(),
+            Some(Message::Text(r#"{"type": "subscribe"}"#.to_owned()))
+        );
+        assert_eq!(
+            parse("hello world").unwrap(),
+            Some(Message::Text("hello world".to_owned()))
+        );
+        assert_eq!(
+            parse("//binary").unwrap(),
+            Some(Message::Text("/binary".to_owned()))
+        );
+        assert_eq!(parse("  ").unwrap(), None);
+        assert_eq!(
+            parse("/binary hello").unwrap(),
+            Some(Message::Binary(b"hello".to_vec()))
+        );
+        assert_eq!(parse("/ping").unwrap(), Some(Message::Ping(vec![])));
+        assert_eq!(
+            parse("/close").unwrap(),
+            Some(Message::Close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "".into()
+            })))
+        );
+        assert_eq!(
+            parse("/close 4000 going away").unwrap(),
+            Some(Message::Close(Some(CloseFrame {
+                code: CloseCode::from(4000),
+                reason: "going away".into()
+            })))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            parse("/close abc").unwrap_err().to_string(),
+            "Invalid close code 'abc'"
+        );
+        assert_eq!(
+            parse("/send hello").unwrap_err().to_string(),
+            format!("Unknown command '/send', expected one of: {}", COMMANDS)
+        );
+    }
+}
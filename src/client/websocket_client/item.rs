@@ -1,6 +1,9 @@
-use crate::client::{prettify, JsonError};
-use std::{fmt, str};
-use thiserror::Error;
+use crate::client::{
+    output::{self, Direction, Record},
+    prettify,
+};
+use serde_json::json;
+use std::{fmt, str, time::Duration};
 use tungstenite::Message;
 
 pub(crate) struct Item {
@@ -8,86 +11,111 @@ pub(crate) struct Item {
     kind: String,
 }
 
-#[derive(Error, Debug)]
-pub(crate) enum ItemError {
-    #[error("Can't convert to string: {err} in payload {payload}")]
-    Utf8Error { err: String, payload: String },
-    #[error("Can't prettify json: {0}")]
-    PrettifyError(String),
-}
-
-impl<'a> From<JsonError<'a>> for ItemError {
-    fn from(err: JsonError) -> Self {
-        Self::PrettifyError(err.to_string())
-    }
-}
-
 impl Item {
     pub fn new(content: String, kind: String) -> Self {
         Self { content, kind }
     }
+
+    pub fn disconnect() -> Self {
+        Self::new("Connection closed".to_string(), "Disconnect".to_string())
+    }
+
+    pub fn reconnect(attempt: u32, max_attempts: Option<u32>, delay: Duration) -> Self {
+        let content = match max_attempts {
+            Some(max_attempts) => format!("Attempt {} of {} in {:?}", attempt, max_attempts, delay),
+            None => format!("Attempt {} in {:?}", attempt, delay),
+        };
+
+        Self::new(content, "Reconnect".to_string())
+    }
 }
 
-impl TryFrom<Message> for Item {
-    type Error = ItemError;
+/// Prettifies json content, and keeps other content, e.g. raw text, as is.
+fn prettify_or_raw(content: &str) -> String {
+    prettify(content).unwrap_or_else(|_| content.to_owned())
+}
+
+/// Renders the payload as text, or as hex bytes if it isn't valid UTF-8, so
+/// that any message can be displayed.
+fn display_payload(payload: &[u8]) -> String {
+    match str::from_utf8(payload) {
+        Ok(content) => prettify_or_raw(content),
+        Err(_) => payload
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
 
-    fn try_from(message: Message) -> Result<Self, Self::Error> {
+impl From<Message> for Item {
+    fn from(message: Message) -> Self {
         match message {
-            Message::Text(data) => {
-                let content = prettify(&data)?;
-                let item = Item::new(content, "Text".to_string());
-                Ok(item)
-            }
-            Message::Binary(ref data) => {
-                let content = str::from_utf8(data).map_err(|err| ItemError::Utf8Error {
-                    err: err.to_string(),
-                    payload: String::from_utf8_lossy(data).to_string(),
-                })?;
-                let content = prettify(content)?;
-                let item = Item::new(content, "Binary".to_string());
-                Ok(item)
-            }
-            Message::Ping(ref data) => {
-                let content = str::from_utf8(data).map_err(|err| ItemError::Utf8Error {
-                    err: err.to_string(),
-                    payload: String::from_utf8_lossy(data).to_string(),
-                })?;
-                let content = prettify(content)?;
-                let item = Item::new(content, "Ping".to_string());
-                Ok(item)
-            }
-            Message::Pong(ref data) => {
-                let content = str::from_utf8(data).map_err(|err| ItemError::Utf8Error {
-                    err: err.to_string(),
-                    payload: String::from_utf8_lossy(data).to_string(),
-                })?;
-                let content = prettify(content)?;
-                let item = Item::new(content, "Pong".to_string());
-                Ok(item)
-            }
+            Message::Text(data) => Item::new(prettify_or_raw(&data), "Text".to_string()),
+            Message::Binary(data) => Item::new(display_payload(&data), "Binary".to_string()),
+            Message::Ping(data) => Item::new(display_payload(&data), "Ping".to_string()),
+            Message::Pong(data) => Item::new(display_payload(&data), "Pong".to_string()),
             Message::Frame(frame) => {
-                let content =
-                    str::from_utf8(frame.payload()).map_err(|err| ItemError::Utf8Error {
-                        err: err.to_string(),
-                        payload: String::from_utf8_lossy(frame.payload()).to_string(),
-                    })?;
-                let content = prettify(content)?;
-                let item = Item::new(content, "Frame".to_string());
-                Ok(item)
+                Item::new(display_payload(frame.payload()), "Frame".to_string())
             }
             Message::Close(Some(frame)) => {
-                let content = frame.reason;
-                let content = prettify(&content)?;
-                let item = Item::new(content, "Close".to_string());
-                Ok(item)
+                Item::new(prettify_or_raw(&frame.reason), "Close".to_string())
             }
-            Message::Close(None) => Ok(Item::new("Close".to_string(), "Close".to_string())),
+            Message::Close(None) => Item::new("Close".to_string(), "Close".to_string()),
         }
     }
 }
 
+/// The record of a sent or received message in the json output formats.
+pub(crate) fn to_record(message: &Message, direction: Direction) -> Record {
+    let (kind, body) = match message {
+        Message::Text(text) => ("text", Some(output::decode_body(text.as_bytes()))),
+        Message::Binary(data) => ("binary", Some(output::decode_body(data))),
+        Message::Ping(data) => ("ping", Some(output::decode_body(data))),
+        Message::Pong(data) => ("pong", Some(output::decode_body(data))),
+        Message::Frame(frame) => ("frame", Some(output::decode_body(frame.payload()))),
+        Message::Close(frame) => (
+            "close",
+            frame
+                .as_ref()
+                .map(|frame| json!({ "code": u16::from(frame.code), "reason": frame.reason })),
+        ),
+    };
+    let record = Record::new("websocket", direction, kind);
+
+    match body {
+        Some(body) => record.body(body),
+        None => record,
+    }
+}
+
 impl fmt::Display for Item {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.kind, self.content)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_message() {
+        assert_eq!(
+            Item::from(Message::Text("hello".to_owned())).to_string(),
+            "Text: hello"
+        );
+        assert_eq!(
+            Item::from(Message::Binary(b"raw".to_vec())).to_string(),
+            "Binary: raw"
+        );
+        assert_eq!(
+            Item::from(Message::Binary(vec![0xff, 0xfe, 0x00])).to_string(),
+            "Binary: ff fe 00"
+        );
+        assert_eq!(
+            Item::from(Message::Ping(vec![0xc3])).to_string(),
+            "Ping: c3"
+        );
+    }
+}
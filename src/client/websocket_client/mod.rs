@@ -1,37 +1,315 @@
 pub mod config;
+mod interactive;
 pub mod item;
+mod steps;
 
-use self::{config::Config, item::Item};
-use crate::client::prettify_json;
-use anyhow::anyhow;
-use futures::{SinkExt, Stream, StreamExt};
+use self::{
+    config::{Config, Reconnect, Step},
+    interactive::Input,
+    item::Item,
+};
+use crate::client::{
+    auth::{self, Authenticator},
+    expect::{Assertion, MessageExpectation},
+    outcome::{self, Outcome, ReceivedMessage},
+    output::{self, Direction, Record},
+    prettify_json,
+    timeout::{with_timeout, TimeoutError},
+};
+use anyhow::{anyhow, Context};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use http::HeaderMap;
 use log::{error, info};
-use std::collections::HashMap;
+use std::{collections::HashMap, future};
+use tokio::time::{self, Instant};
+use tungstenite::client::IntoClientRequest;
 pub use tungstenite::{Error, Message};
 use url::Url;
 
-pub async fn use_websocket_client(config_file: String) -> Result<(), anyhow::Error> {
+/// Runs the websocket client. Received messages are only kept for the outcome
+/// with `keep_messages`, as subscriptions may run for a long time.
+pub async fn use_websocket_client(
+    config_file: String,
+    keep_messages: bool,
+) -> Result<Outcome, anyhow::Error> {
     info!("Using websocket client");
 
-    let config = serde_json::from_str(&config_file)?;
+    let config: Config = serde_json::from_str(&config_file)?;
+    let run_timeout = config.timeout.run();
+    let input = if config.interactive {
+        info!(
+            "Sending lines of stdin as messages, or commands {}",
+            interactive::COMMANDS
+        );
 
-    let mut stream = connect(config).await?;
+        Some(interactive::input())
+    } else {
+        None
+    };
+
+    with_timeout(
+        run_timeout,
+        TimeoutError::Run,
+        receive(config, input, keep_messages),
+    )
+    .await?
+}
+
+/// Receives messages until the connection is closed, while sending the lines
+/// of the input.
+async fn receive(
+    config: Config,
+    mut input: Option<Input>,
+    keep_messages: bool,
+) -> Result<Outcome, anyhow::Error> {
+    let idle_timeout = config.timeout.idle();
+    let expected_message = config
+        .expect
+        .as_ref()
+        .and_then(|expect| expect.message.clone());
+    // The authenticator is shared by all connections, so that cached tokens
+    // are reused when reconnecting
+    let authenticator = config.auth.to_owned().map(Authenticator::new);
+
+    let (mut sink, mut stream) = connect(&config, authenticator.as_ref(), None).await?;
+    let start = Instant::now();
+    let within = expected_message
+        .as_ref()
+        .and_then(MessageExpectation::within);
+    let mut received = Received::new(start, keep_messages);
+    let steps = steps::run(
+        &config.subscription.steps,
+        &mut sink,
+        &mut stream,
+        idle_timeout,
+        &mut received,
+    )
+    .await?;
+    // The steps close the connection with a close step, or after a failed
+    // step, and the client doesn't reconnect then
+    let mut is_closing = !steps.iter().all(Assertion::is_passed)
+        || config
+            .subscription
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Close(_)));
 
     info!("Receiving stream");
 
-    while let Some(item) = stream.next().await {
+    loop {
+        let item = tokio::select! {
+            item = with_timeout(idle_timeout, TimeoutError::Idle, stream.next()) => item?,
+            line = interactive::next_line(&mut input) => {
+                let message = match line? {
+                    Some(line) => match interactive::parse(&line) {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            error!("{}", err);
+                            continue;
+                        }
+                    },
+                    // The connection is closed at the end of the input, but
+                    // messages are received until the server closes it too
+                    None => Message::Close(None),
+                };
+
+                if message.is_close() {
+                    input = None;
+                    is_closing = true;
+                }
+
+                send(&mut sink, message).await?;
+                continue;
+            }
+            // Stop receiving if the expected message can't arrive in time
+            // anymore
+            () = sleep_until(within.map(|within| start + within)) => {
+                info!("Expected message not received within {:?}", within.unwrap_or_default());
+                break;
+            }
+        };
+
+        let item = match item {
+            Some(item) => item,
+            None => match &config.reconnect {
+                Some(reconnect) if !is_closing && reconnect.is_enabled() => {
+                    print_event(
+                        Item::disconnect(),
+                        Record::new("websocket", Direction::Received, "disconnect"),
+                    );
+
+                    (sink, stream) =
+                        connect(&config, authenticator.as_ref(), Some(reconnect)).await?;
+                    continue;
+                }
+                _ => break,
+            },
+        };
+
         match item {
-            Ok(msg) => println!("{}", Item::try_from(msg)?),
+            Ok(msg) => {
+                if let Some(message) = received.collect(msg) {
+                    // Stop receiving as the expectation is met
+                    if expected_message
+                        .as_ref()
+                        .is_some_and(|expected| expected.is_matched_by(message))
+                    {
+                        info!("Received expected message");
+                        break;
+                    }
+                }
+            }
             Err(err) => error!("{}", err),
         }
     }
 
-    Ok(())
+    Ok(Outcome::Websocket {
+        messages: received.into_messages(),
+        steps,
+    })
+}
+
+/// Prints the disconnect or reconnect as item, or emits it as record.
+fn print_event(item: Item, record: Record) {
+    if output::is_text() {
+        println!("{}", item);
+    } else {
+        output::emit(record);
+    }
+}
+
+/// Prints the message and sends it.
+async fn send<S>(sink: &mut S, message: Message) -> Result<(), anyhow::Error>
+where
+    S: Sink<Message, Error = Error> + Unpin,
+{
+    if output::is_text() {
+        info!("Sending message\n{}", Item::from(message.to_owned()));
+    } else {
+        output::emit(item::to_record(&message, Direction::Sent));
+    }
+
+    sink.send(message).await.context("Can't send message")
+}
+
+/// The received text and binary messages. Unless all messages are kept, only
+/// the last one is kept to match it against expectations and steps.
+struct Received {
+    start: Instant,
+    keep_all: bool,
+    messages: Vec<ReceivedMessage>,
+}
+
+impl Received {
+    fn new(start: Instant, keep_all: bool) -> Self {
+        Self {
+            start,
+            keep_all,
+            messages: vec![],
+        }
+    }
+
+    /// Prints the received message, and collects it if it's a text or binary
+    /// message.
+    fn collect(&mut self, msg: Message) -> Option<&ReceivedMessage> {
+        let payload = match &msg {
+            Message::Text(text) => Some(outcome::parse_payload(text.as_bytes())),
+            Message::Binary(data) => Some(outcome::parse_payload(data)),
+            _ => None,
+        };
+
+        if output::is_text() {
+            println!("{}", Item::from(msg));
+        } else {
+            output::emit(item::to_record(&msg, Direction::Received));
+        }
+
+        let payload = payload?;
+
+        if !self.keep_all {
+            self.messages.clear();
+        }
+
+        self.messages.push(ReceivedMessage {
+            payload,
+            elapsed: self.start.elapsed(),
+        });
+
+        self.messages.last()
+    }
+
+    fn into_messages(self) -> Vec<ReceivedMessage> {
+        if self.keep_all {
+            self.messages
+        } else {
+            vec![]
+        }
+    }
 }
 
+/// Sleeps until the deadline, or forever without deadline.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+/// Connects to the websocket and sends the subscription messages. Returns the
+/// sink for sending and the stream for receiving messages. With reconnect
+/// policy, the connection is attempted until the maximum attempts, waiting
+/// for the backoff before each attempt.
 async fn connect(
-    config: Config,
-) -> Result<impl Stream<Item = Result<Message, Error>>, anyhow::Error> {
+    config: &Config,
+    authenticator: Option<&Authenticator>,
+    reconnect: Option<&Reconnect>,
+) -> Result<
+    (
+        impl Sink<Message, Error = Error>,
+        impl Stream<Item = Result<Message, Error>>,
+    ),
+    anyhow::Error,
+> {
+    let reconnect = match reconnect {
+        Some(reconnect) => reconnect,
+        None => return open(config, authenticator).await,
+    };
+    let mut attempt = 1;
+
+    loop {
+        let delay = reconnect.delay(attempt);
+
+        print_event(
+            Item::reconnect(attempt, reconnect.max_attempts, delay),
+            Record::new("websocket", Direction::Sent, "reconnect")
+                .detail("attempt", attempt)
+                .detail("delay", delay.as_secs_f64()),
+        );
+        time::sleep(delay).await;
+
+        match open(config, authenticator).await {
+            Ok(connection) => return Ok(connection),
+            Err(err) if reconnect.is_exhausted(attempt) => {
+                return Err(err.context(format!("Can't reconnect after {} attempts", attempt)))
+            }
+            Err(err) => error!("Can't reconnect: {:#}", err),
+        }
+
+        attempt += 1;
+    }
+}
+
+async fn open(
+    config: &Config,
+    authenticator: Option<&Authenticator>,
+) -> Result<
+    (
+        impl Sink<Message, Error = Error>,
+        impl Stream<Item = Result<Message, Error>>,
+    ),
+    anyhow::Error,
+> {
     let mut base_url = Url::parse(&format!("{}{}", &config.api.url, &config.api.endpoint))?;
 
     let mut key_value_pairs = HashMap::new();
@@ -58,9 +336,36 @@ async fn connect(
 
     base_url.set_query(url_encoded.as_deref());
 
-    info!("Connecting to websocket at url '{}'", base_url);
+    let credentials = match authenticator {
+        Some(authenticator) => Some(authenticator.credentials().await?),
+        None => None,
+    };
+
+    if let Some(credentials) = &credentials {
+        credentials.add_to_url(&mut base_url);
+    }
+
+    let mut request = base_url.as_str().into_client_request()?;
+
+    if let Some(credentials) = &credentials {
+        credentials.add_to_headers(request.headers_mut());
+    }
+
+    let mut redacted_url = base_url.to_owned();
+    auth::redact(
+        &mut HeaderMap::new(),
+        &mut redacted_url,
+        credentials.as_ref(),
+    );
+
+    info!("Connecting to websocket at url '{}'", redacted_url);
 
-    let (socket, response) = tokio_tungstenite::connect_async(base_url).await?;
+    let (socket, response) = with_timeout(
+        config.timeout.connect(),
+        TimeoutError::Connect,
+        tokio_tungstenite::connect_async(request),
+    )
+    .await??;
 
     // Check if protocol was changed to websocket protocol (see
     // https://www.iana.org/assignments/http-status-codes/http-status-codes.xhtml)
@@ -68,20 +373,30 @@ async fn connect(
         info!("Switching to websocket protocol");
         info!("Connection to websocket established");
 
+        output::emit(
+            Record::new("websocket", Direction::Received, "handshake")
+                .detail("status", response.status().as_u16())
+                .headers(response.headers()),
+        );
+
         let (mut sink, stream) = socket.split();
 
-        if let Some(subscription_request) = config.subscription.request {
+        for subscription_request in config.subscription.requests() {
             let subscription_message = Message::Text(subscription_request.to_string());
 
-            info!(
-                "Sending subscription message\nMessage: {}",
-                prettify_json(subscription_request)?
-            );
+            if output::is_text() {
+                info!(
+                    "Sending subscription message\nMessage: {}",
+                    prettify_json(subscription_request.to_owned())?
+                );
+            } else {
+                output::emit(item::to_record(&subscription_message, Direction::Sent));
+            }
 
             sink.send(subscription_message).await?;
         }
 
-        Ok(stream)
+        Ok((sink, stream))
     } else {
         Err(anyhow!(format!(
             "Invalid status code {} for websocket response",
@@ -89,3 +404,223 @@ async fn connect(
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::http_client::test_server;
+    use futures::channel::mpsc;
+    use hyper::Body;
+    use serde_json::json;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Serves a websocket, which sends a welcome message after connecting,
+    /// and echoes text and binary messages. After the `last` message, it
+    /// sends one more message before reading the close frame. The received messages are
+    /// sent to the returned receiver.
+    async fn serve() -> (String, mpsc::UnboundedReceiver<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::unbounded();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            socket
+                .send(Message::Text("welcome".to_owned()))
+                .await
+                .unwrap();
+
+            while let Some(Ok(msg)) = socket.next().await {
+                sender.unbounded_send(msg.to_owned()).unwrap();
+
+                match msg {
+                    Message::Text(text) => {
+                        socket
+                            .send(Message::Text(format!("echo {}", text)))
+                            .await
+                            .unwrap();
+
+                        if text == "last" {
+                            socket.send(Message::Text("late".to_owned())).await.unwrap();
+                        }
+                    }
+                    Message::Binary(data) => socket.send(Message::Binary(data)).await.unwrap(),
+                    _ => (),
+                }
+            }
+        });
+
+        (format!("ws://{}", addr), receiver)
+    }
+
+    #[tokio::test]
+    async fn test_receive_interactive() {
+        let (url, server_received) = serve().await;
+        let config: Config = serde_json::from_value(json!({
+            "api": { "url": url, "endpoint": "/" },
+            "subscription": {},
+            "interactive": true,
+            "timeout": { "run": 5 }
+        }))
+        .unwrap();
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let reader: Box<dyn AsyncBufRead + Send + Unpin> = Box::new(BufReader::new(reader));
+
+        // The lines are written after the welcome message is received, and
+        // the end of the input closes the connection
+        let write = async move {
+            time::sleep(Duration::from_millis(300)).await;
+            writer.write_all(b"first\n\n/ping\n").await.unwrap();
+            writer.write_all(b"last\n").await.unwrap();
+        };
+        let (outcome, ()) = tokio::join!(receive(config, Some(reader.lines()), true), write);
+
+        let messages = match outcome.unwrap() {
+            Outcome::Websocket { messages, .. } => messages,
+            _ => panic!("Expected websocket outcome"),
+        };
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.payload.to_owned())
+                .collect::<Vec<_>>(),
+            vec![
+                json!("welcome"),
+                json!("echo first"),
+                json!("echo last"),
+                json!("late")
+            ]
+        );
+        assert!(messages[0].elapsed < Duration::from_millis(300));
+        assert_eq!(
+            server_received.collect::<Vec<_>>().await,
+            vec![
+                Message::Text("first".to_owned()),
+                Message::Ping(vec![]),
+                Message::Text("last".to_owned()),
+                Message::Close(None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8() {
+        let (url, server_received) = serve().await;
+        let config: Config = serde_json::from_value(json!({
+            "api": { "url": url, "endpoint": "/" },
+            "subscription": {}
+        }))
+        .unwrap();
+        let data = vec![0xff, 0xfe, 0x00];
+
+        let (mut sink, mut stream) = connect(&config, None, None).await.unwrap();
+        send(&mut sink, Message::Binary(data.to_owned()))
+            .await
+            .unwrap();
+        let mut received = Received::new(Instant::now(), true);
+        let _ = received.collect(stream.next().await.unwrap().unwrap());
+        let message = received.collect(stream.next().await.unwrap().unwrap());
+
+        // The echoed message is displayed and collected without error
+        assert_eq!(
+            message.map(|message| message.payload.to_owned()),
+            Some(outcome::parse_payload(&data))
+        );
+        send(&mut sink, Message::Close(None)).await.unwrap();
+        while stream.next().await.is_some() {}
+        assert_eq!(
+            server_received.collect::<Vec<_>>().await,
+            vec![Message::Binary(data), Message::Close(None)]
+        );
+    }
+
+    /// Serves a websocket, which sends one message on each connection, and
+    /// drops the connection without closing handshake.
+    async fn serve_and_drop(messages: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            for message in messages {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                socket
+                    .send(Message::Text(message.to_owned()))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        format!("ws://{}", addr)
+    }
+
+    fn payloads(outcome: Outcome) -> Vec<serde_json::Value> {
+        match outcome {
+            Outcome::Websocket { messages, .. } => messages
+                .into_iter()
+                .map(|message| message.payload)
+                .collect(),
+            _ => panic!("Expected websocket outcome"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_cached_token() {
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let received = token_requests.clone();
+        let token_addr = test_server::spawn(false, move |_| {
+            received.fetch_add(1, Ordering::SeqCst);
+            let token =
+                json!({ "access_token": "token", "token_type": "Bearer", "expires_in": 3600 });
+
+            async move { hyper::Response::new(Body::from(token.to_string())) }
+        });
+        let url = serve_and_drop(vec!["first", "second"]).await;
+        let config: Config = serde_json::from_value(json!({
+            "api": { "url": url, "endpoint": "/" },
+            "subscription": {},
+            "auth": {
+                "type": "oauth2_client_credentials",
+                "token_url": format!("http://{}/token", token_addr),
+                "client_id": "id",
+                "client_secret": "secret"
+            },
+            "reconnect": { "max_attempts": 1, "backoff": 0.01 },
+            "expect": { "message": { "matches": "second" } },
+            "timeout": { "run": 5 }
+        }))
+        .unwrap();
+
+        let outcome = receive(config, None, true).await.unwrap();
+
+        assert_eq!(payloads(outcome), vec![json!("first"), json!("second")]);
+        assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_reconnect_with_zero_attempts() {
+        let url = serve_and_drop(vec!["first"]).await;
+        let config: Config = serde_json::from_value(json!({
+            "api": { "url": url, "endpoint": "/" },
+            "subscription": {},
+            "reconnect": { "max_attempts": 0 },
+            "timeout": { "run": 5 }
+        }))
+        .unwrap();
+
+        let outcome = receive(config, None, true).await.unwrap();
+
+        assert_eq!(payloads(outcome), vec![json!("first")]);
+    }
+}
@@ -0,0 +1,393 @@
+//! Scripted conversations, where the steps of the subscription are executed
+//! in order after connecting, e.g. to send an `init` message and wait for the
+//! `ack` before subscribing. Each step is reported as passed or failed, and
+//! the steps after a failed step are skipped.
+
+use super::{
+    config::{CloseStep, ExpectStep, Step},
+    Received,
+};
+use crate::client::{
+    expect::{self, Assertion},
+    timeout::{with_timeout, TimeoutError},
+};
+use futures::{Sink, Stream, StreamExt};
+use log::{error, info};
+use std::time::Duration;
+use tokio::time;
+use tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Error, Message,
+};
+
+/// Executes the steps, and returns their results. Received messages are
+/// collected into received.
+pub async fn run<S, T>(
+    steps: &[Step],
+    sink: &mut S,
+    stream: &mut T,
+    idle_timeout: Option<Duration>,
+    received: &mut Received,
+) -> Result<Vec<Assertion>, anyhow::Error>
+where
+    S: Sink<Message, Error = Error> + Unpin,
+    T: Stream<Item = Result<Message, Error>> + Unpin,
+{
+    let mut assertions = vec![];
+    let mut steps = steps.iter().enumerate();
+
+    for (i, step) in steps.by_ref() {
+        let description = format!("step {}: {}", i + 1, describe(step));
+        info!("Running {}", description);
+
+        let failure = match step {
+            Step::Send(value) => {
+                let text = match value {
+                    serde_json::Value::String(text) => text.to_owned(),
+                    value => value.to_string(),
+                };
+
+                super::send(sink, Message::Text(text)).await?;
+                None
+            }
+            Step::Expect(step) => expect(step, stream, idle_timeout, received).await?,
+            Step::Sleep(seconds) => {
+                time::sleep(Duration::from_secs_f64(*seconds)).await;
+                None
+            }
+            Step::Close(step) => {
+                super::send(sink, close_message(step.as_ref())).await?;
+                None
+            }
+        };
+
+        let is_failed = failure.is_some();
+        assertions.push(Assertion::new(description, failure));
+
+        if is_failed {
+            break;
+        }
+    }
+
+    let skipped = steps
+        .map(|(i, step)| {
+            Assertion::new(
+                format!("step {}: {}", i + 1, describe(step)),
+                Some("skipped after failed step".to_owned()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // The connection is closed after a failed step, also if it was the last
+    // one, but messages are received until the server closes it too. It may
+    // be closed already, e.g. if the failed step expected more messages.
+    if assertions
+        .last()
+        .is_some_and(|assertion| !assertion.is_passed())
+    {
+        if let Err(err) = super::send(sink, close_message(None)).await {
+            info!("{:#}", err);
+        }
+    }
+
+    assertions.extend(skipped);
+
+    Ok(assertions)
+}
+
+/// Receives messages until the expected number of messages match, and
+/// returns the failure if they don't.
+async fn expect<T>(
+    step: &ExpectStep,
+    stream: &mut T,
+    idle_timeout: Option<Duration>,
+    received: &mut Received,
+) -> Result<Option<String>, anyhow::Error>
+where
+    T: Stream<Item = Result<Message, Error>> + Unpin,
+{
+    let mut matched = 0;
+
+    let receive = async {
+        while matched < step.count {
+            let item = match with_timeout(idle_timeout, TimeoutError::Idle, stream.next()).await? {
+                Some(item) => item,
+                None => return Ok(false),
+            };
+
+            match item {
+                Ok(msg) => {
+                    if let Some(message) = received.collect(msg) {
+                        if expect::is_match(&message.payload, &step.matches) {
+                            matched += 1;
+                        }
+                    }
+                }
+                Err(err) => error!("{}", err),
+            }
+        }
+
+        Ok::<_, anyhow::Error>(true)
+    };
+
+    let received = match step.timeout {
+        Some(timeout) => time::timeout(Duration::from_secs_f64(timeout), receive)
+            .await
+            .ok(),
+        None => Some(receive.await),
+    };
+
+    let failure = match received.transpose()? {
+        Some(true) => None,
+        Some(false) => Some(format!(
+            "connection closed after {} of {} matching messages",
+            matched, step.count
+        )),
+        None => Some(format!(
+            "received {} of {} matching messages",
+            matched, step.count
+        )),
+    };
+
+    Ok(failure)
+}
+
+fn close_message(step: Option<&CloseStep>) -> Message {
+    let (code, reason) = match step {
+        Some(step) => (step.code, step.reason.as_str()),
+        None => (None, ""),
+    };
+
+    Message::Close(Some(CloseFrame {
+        code: code.map_or(CloseCode::Normal, CloseCode::from),
+        reason: reason.to_owned().into(),
+    }))
+}
+
+fn describe(step: &Step) -> String {
+    match step {
+        Step::Send(value) => format!("send {}", value),
+        Step::Expect(step) => {
+            let mut description = match step.count {
+                1 => format!("expect message matching {}", step.matches),
+                count => format!("expect {} messages matching {}", count, step.matches),
+            };
+
+            if let Some(timeout) = step.timeout {
+                description.push_str(&format!(" within {}s", timeout));
+            }
+
+            description
+        }
+        Step::Sleep(seconds) => format!("sleep {}s", seconds),
+        Step::Close(step) => format!(
+            "close with code {}",
+            step.as_ref().and_then(|step| step.code).unwrap_or(1000)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc, stream, SinkExt};
+    use serde_json::json;
+    use tokio::time::Instant;
+
+    fn steps(steps: serde_json::Value) -> Vec<Step> {
+        serde_json::from_value(steps).unwrap()
+    }
+
+    async fn results(steps: &[Step], received: Vec<&str>) -> (Vec<String>, Vec<Message>) {
+        let (sink, sent) = mpsc::unbounded();
+        let mut sink = sink.sink_map_err(|_| Error::ConnectionClosed);
+        let received = received
+            .into_iter()
+            .map(|text| Message::Text(text.to_owned()));
+        let mut stream = stream::iter(received).map(Ok);
+        let mut received = Received::new(Instant::now(), true);
+
+        let assertions = run(steps, &mut sink, &mut stream, None, &mut received)
+            .await
+            .unwrap();
+        drop(sink);
+
+        (
+            assertions.iter().map(Assertion::to_string).collect(),
+            sent.collect().await,
+        )
+    }
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(
+            steps(json!([
+                { "send": { "type": "connection_init" } },
+                { "expect": { "matches": { "type": "connection_ack" }, "timeout": 5 } },
+                { "sleep": 0.5 },
+                { "close": null },
+                { "close": { "code": 4000, "reason": "done" } }
+            ])),
+            vec![
+                Step::Send(json!({ "type": "connection_init" })),
+                Step::Expect(ExpectStep {
+                    matches: json!({ "type": "connection_ack" }),
+                    count: 1,
+                    timeout: Some(5.0),
+                }),
+                Step::Sleep(0.5),
+                Step::Close(None),
+                Step::Close(Some(CloseStep {
+                    code: Some(4000),
+                    reason: "done".to_owned(),
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid_seconds() {
+        for steps in [
+            json!([{ "sleep": -1 }]),
+            json!([{ "expect": { "matches": {}, "timeout": 1e300 } }]),
+        ] {
+            let err = serde_json::from_value::<Vec<Step>>(steps).unwrap_err();
+            assert!(err.to_string().starts_with("invalid seconds"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let steps = steps(json!([
+            { "send": { "type": "connection_init" } },
+            { "expect": { "matches": { "type": "connection_ack" } } },
+            { "send": "raw text" },
+            { "expect": { "matches": { "type": "next" }, "count": 2 } },
+            { "close": null }
+        ]));
+        let received = vec![
+            r#"{"type": "ka"}"#,
+            r#"{"type": "connection_ack"}"#,
+            r#"{"type": "next", "id": 1}"#,
+            r#"{"type": "ka"}"#,
+            r#"{"type": "next", "id": 2}"#,
+        ];
+
+        let (results, sent) = results(&steps, received).await;
+
+        assert_eq!(
+            results,
+            vec![
+                "PASS step 1: send {\"type\":\"connection_init\"}",
+                "PASS step 2: expect message matching {\"type\":\"connection_ack\"}",
+                "PASS step 3: send \"raw text\"",
+                "PASS step 4: expect 2 messages matching {\"type\":\"next\"}",
+                "PASS step 5: close with code 1000",
+            ]
+        );
+        assert_eq!(
+            sent,
+            vec![
+                Message::Text(r#"{"type":"connection_init"}"#.to_owned()),
+                Message::Text("raw text".to_owned()),
+                close_message(None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_failed() {
+        let steps = steps(json!([
+            { "expect": { "matches": { "type": "next" }, "count": 2 } },
+            { "send": { "type": "complete" } }
+        ]));
+
+        let (results, sent) = results(&steps, vec![r#"{"type": "next"}"#]).await;
+
+        assert_eq!(
+            results,
+            vec![
+                "FAIL step 1: expect 2 messages matching {\"type\":\"next\"}: connection closed after 1 of 2 matching messages",
+                "FAIL step 2: send {\"type\":\"complete\"}: skipped after failed step",
+            ]
+        );
+        assert_eq!(sent, vec![close_message(None)]);
+    }
+
+    #[tokio::test]
+    async fn test_run_without_keeping_messages() {
+        let steps = steps(json!([
+            { "expect": { "matches": { "type": "next" }, "count": 2 } }
+        ]));
+        let (sink, _sent) = mpsc::unbounded();
+        let mut sink = sink.sink_map_err(|_| Error::ConnectionClosed);
+        let received = [
+            r#"{"type": "next", "id": 1}"#,
+            r#"{"type": "next", "id": 2}"#,
+        ]
+        .map(|text| Message::Text(text.to_owned()));
+        let mut stream = stream::iter(received).map(Ok);
+        let mut received = Received::new(Instant::now(), false);
+
+        let assertions = run(&steps, &mut sink, &mut stream, None, &mut received)
+            .await
+            .unwrap();
+
+        assert!(assertions[0].is_passed());
+        assert_eq!(received.messages.len(), 1);
+        assert_eq!(
+            received.messages[0].payload,
+            json!({ "type": "next", "id": 2 })
+        );
+        assert!(received.into_messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_failed_with_closed_connection() {
+        let steps = steps(json!([
+            { "expect": { "matches": { "type": "next" } } },
+            { "send": { "type": "complete" } }
+        ]));
+        // The close frame after the failed step is rejected by the sink
+        let (sink, sent) = mpsc::unbounded();
+        drop(sent);
+        let mut sink = sink.sink_map_err(|_| Error::ConnectionClosed);
+        let mut stream = stream::empty();
+        let mut received = Received::new(Instant::now(), true);
+
+        let assertions = run(&steps, &mut sink, &mut stream, None, &mut received)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            assertions.iter().map(Assertion::to_string).collect::<Vec<_>>(),
+            vec![
+                "FAIL step 1: expect message matching {\"type\":\"next\"}: connection closed after 0 of 1 matching messages",
+                "FAIL step 2: send {\"type\":\"complete\"}: skipped after failed step",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout() {
+        let steps = steps(json!([
+            { "expect": { "matches": { "type": "ack" }, "timeout": 0.01 } }
+        ]));
+        let (sink, sent) = mpsc::unbounded();
+        let mut sink = sink.sink_map_err(|_| Error::ConnectionClosed);
+        let mut stream = stream::pending();
+
+        let mut received = Received::new(Instant::now(), true);
+
+        let assertions = run(&steps, &mut sink, &mut stream, None, &mut received)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            assertions[0].to_string(),
+            "FAIL step 1: expect message matching {\"type\":\"ack\"} within 0.01s: received 0 of 1 matching messages"
+        );
+        drop(sink);
+        assert_eq!(sent.collect::<Vec<_>>().await, vec![close_message(None)]);
+    }
+}
@@ -1,31 +1,54 @@
+mod cli;
 mod client;
 
-use crate::client::{
-    client_type::ClientType, grpc_client::use_grpc_client, http_client::use_http_client,
-    websocket_client::use_websocket_client, Client,
+use crate::{
+    cli::Cli,
+    client::{collection, expect, interpolation, output, timeout::TimeoutError, use_client},
 };
 use anyhow::Context;
-use log::info;
-use std::{env, fs, path::Path};
+use clap::Parser;
+use log::error;
+use std::{path::Path, process};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     logger::start().context("Can't set up logging")?;
 
-    let config_path =
-        env::var("CONFIG_PATH").context("Missing environment variable 'CONFIG_PATH'")?;
-    info!("Reading file '{}'", config_path);
-    let config_path = Path::new(&config_path);
-    let config_file = fs::read_to_string(config_path).context("Can't read file")?;
+    if let Err(err) = run().await {
+        // Exit with a distinct exit code for each kind of timeout
+        if let Some(timeout_error) = err.downcast_ref::<TimeoutError>() {
+            error!("{:#}", err);
+            process::exit(timeout_error.exit_code());
+        }
 
-    let client: Client =
-        serde_json::from_str(&config_file).context("Can't deserialize client value")?;
-
-    match client.client_type {
-        ClientType::Http => use_http_client(config_file).await?,
-        ClientType::Websocket => use_websocket_client(config_file).await?,
-        ClientType::Grpc => use_grpc_client(config_file).await?,
+        return Err(err);
     }
 
     Ok(())
 }
+
+async fn run() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    output::set_format(cli.output);
+
+    // The environment is selected and flags are applied before placeholders
+    // are replaced and the config is deserialized by the client
+    let config = cli.config()?;
+    let variables = interpolation::variables(Path::new(interpolation::ENV_FILE))?;
+
+    // The requests of a collection are interpolated one by one, as they may
+    // use values of previous responses
+    if collection::is_collection(&config) {
+        return collection::run(config, variables, cli.request.as_deref()).await;
+    }
+
+    let config_file = interpolation::interpolate(config, &variables)
+        .context("Can't interpolate config")?
+        .to_string();
+
+    let keep_messages = expect::is_expected(&config_file)?;
+    let outcome = use_client(config_file.to_owned(), keep_messages).await?;
+    let assertions = expect::check(&config_file, &outcome)?;
+
+    expect::report(&[(None, assertions)])
+}
@@ -0,0 +1,78 @@
+//! Runs the binary with json output, where stdout must only contain the
+//! records, and log messages must be written to stderr.
+
+use serde_json::{json, Value};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    process::Command,
+    thread,
+};
+
+/// Serves a single http response with a json body, and returns the url.
+fn serve() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        let _ = stream.read(&mut buf).unwrap();
+        let body = r#"{"hello":"world"}"#;
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+    });
+
+    format!("http://{}/hello", addr)
+}
+
+#[test]
+fn test_ndjson_output_on_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("config.json");
+    let config = json!({
+        "client": "http",
+        "api": { "url": serve() },
+        "request": { "method": "GET" },
+        "expect": { "status": 200 }
+    });
+    std::fs::write(&config_path, config.to_string()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_any-client"))
+        .args(["--output", "ndjson", "http", "--config"])
+        .arg(&config_path)
+        .env("RUST_LOG", "debug")
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect(line))
+        .collect::<Vec<_>>();
+    let kinds = records
+        .iter()
+        .map(|record| (record["direction"].as_str(), record["kind"].as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        kinds,
+        vec![
+            (Some("sent"), Some("request")),
+            (Some("received"), Some("response"))
+        ]
+    );
+    assert_eq!(records[0]["method"], "GET");
+    assert_eq!(records[1]["body"], json!({ "hello": "world" }));
+}